@@ -0,0 +1,18 @@
+mod edges;
+mod imp;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+    pub struct CpuCanny(ObjectSubclass<imp::CpuCanny>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekacpucanny",
+        gst::Rank::NONE,
+        CpuCanny::static_type(),
+    )
+}
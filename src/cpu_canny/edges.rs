@@ -0,0 +1,313 @@
+//!
+//! Non-maximum suppression, automatic thresholds and hysteresis of the gradient magnitude
+//!
+
+use std::ops::Range;
+
+/// Number of histogram bins used to find the Otsu threshold
+const OTSU_BINS: usize = 256;
+
+/// Gradient direction rounded to one of the four neighbour pairs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Horizontal,
+    Vertical,
+    /// From top left to bottom right
+    Diagonal,
+    /// From top right to bottom left
+    AntiDiagonal,
+}
+
+impl Direction {
+    /// Rounds the direction of `(gx, gy)` where `gy` is positive when the intensity grows upwards
+    pub fn new(gx: i32, gy: i32) -> Self {
+        // tan(22.5°) and tan(67.5°) in 16.16 fixed point
+        const TAN_22_5: i64 = 27146;
+        const TAN_67_5: i64 = 158218;
+
+        let (x, y) = ((gx as i64).abs(), (gy as i64).abs() << 16);
+        if y <= x * TAN_22_5 {
+            Direction::Horizontal
+        } else if y > x * TAN_67_5 {
+            Direction::Vertical
+        } else if (gx > 0) != (gy > 0) {
+            // Growing to the right and downwards
+            Direction::Diagonal
+        } else {
+            Direction::AntiDiagonal
+        }
+    }
+
+    /// Offsets `(dx, dy)` of the neighbours along the gradient
+    pub fn neighbours(self) -> [(isize, isize); 2] {
+        match self {
+            Direction::Horizontal => [(-1, 0), (1, 0)],
+            Direction::Vertical => [(0, -1), (0, 1)],
+            Direction::Diagonal => [(-1, -1), (1, 1)],
+            Direction::AntiDiagonal => [(1, -1), (-1, 1)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gradient {
+    pub magnitude: f32,
+    pub direction: Direction,
+}
+
+impl Gradient {
+    pub fn new(gx: i32, gy: i32) -> Self {
+        Self {
+            magnitude: (gx as f32).hypot(gy as f32),
+            direction: Direction::new(gx, gy),
+        }
+    }
+}
+
+/// Writes magnitude of the local maxima along the gradient of `lines` into `out_stripe`,
+/// every other pixel gets zero
+///
+/// `gradients` is the whole frame of `width` x `height`, `out_stripe` starts at the first line
+pub fn suppress(
+    gradients: &[Gradient],
+    width: usize,
+    height: usize,
+    lines: Range<usize>,
+    out_stripe: &mut [f32],
+) {
+    let magnitude = |x: usize, y: usize, (dx, dy): (isize, isize)| match (
+        x.checked_add_signed(dx),
+        y.checked_add_signed(dy),
+    ) {
+        (Some(x), Some(y)) if x < width && y < height => gradients[y * width + x].magnitude,
+        _ => 0.0,
+    };
+
+    for y in lines.clone() {
+        let out_line = &mut out_stripe[(y - lines.start) * width..][..width];
+        for (x, out) in out_line.iter_mut().enumerate() {
+            let gradient = gradients[y * width + x];
+            let [prev, next] = gradient.direction.neighbours();
+
+            // Plateaus keep only their first pixel, so edges stay one pixel wide
+            let is_maximum = gradient.magnitude > magnitude(x, y, prev)
+                && gradient.magnitude >= magnitude(x, y, next);
+            *out = if is_maximum { gradient.magnitude } else { 0.0 };
+        }
+    }
+}
+
+/// Otsu threshold of the non-zero `magnitudes`, it splits them into weak and strong edges with
+/// the largest between-class variance
+pub fn otsu(magnitudes: &[f32]) -> f32 {
+    let max = magnitudes.iter().copied().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return 0.0;
+    }
+
+    let bin_width = max / (OTSU_BINS - 1) as f32;
+    let mut histogram = [0u64; OTSU_BINS];
+    for &magnitude in magnitudes.iter().filter(|&&x| x > 0.0) {
+        histogram[(magnitude / bin_width) as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let total_sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| (bin as u64 * count) as f64)
+        .sum();
+
+    let (mut below, mut below_sum) = (0u64, 0.0f64);
+    let (mut best_bin, mut best_variance) = (0, 0.0f64);
+    for (bin, &count) in histogram.iter().enumerate() {
+        below += count;
+        below_sum += (bin as u64 * count) as f64;
+        let above = total - below;
+        if below == 0 {
+            continue;
+        }
+        if above == 0 {
+            break;
+        }
+
+        let mean_below = below_sum / below as f64;
+        let mean_above = (total_sum - below_sum) / above as f64;
+        let variance = below as f64 * above as f64 * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            best_bin = bin;
+            best_variance = variance;
+        }
+    }
+
+    // Upper edge of the last bin of the weak class
+    (best_bin + 1) as f32 * bin_width
+}
+
+/// Writes 255 for edge pixels and 0 for the rest into `out_plane` with `out_stride` bytes per line
+///
+/// Pixels of `suppressed` magnitude above `high` are edges, as are pixels above `low` connected
+/// to them through other edge pixels
+pub fn hysteresis(
+    suppressed: &[f32],
+    width: usize,
+    height: usize,
+    low: f32,
+    high: f32,
+    out_plane: &mut [u8],
+    out_stride: usize,
+) {
+    const EDGE: u8 = u8::MAX;
+
+    for out_line in out_plane.chunks_mut(out_stride).take(height) {
+        out_line[..width].fill(0);
+    }
+
+    let mut stack = Vec::new();
+    for (start, _) in suppressed.iter().enumerate().filter(|(_, &x)| x > high) {
+        let (x, y) = (start % width, start / width);
+        if out_plane[y * out_stride + x] == EDGE {
+            continue;
+        }
+
+        out_plane[y * out_stride + x] = EDGE;
+        stack.push((x, y));
+
+        while let Some((x, y)) = stack.pop() {
+            let xs = x.saturating_sub(1)..(x + 2).min(width);
+            let ys = y.saturating_sub(1)..(y + 2).min(height);
+
+            for ny in ys {
+                for nx in xs.clone() {
+                    let out = &mut out_plane[ny * out_stride + nx];
+                    if *out != EDGE && suppressed[ny * width + nx] > low {
+                        *out = EDGE;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradients(magnitudes: &[f32], direction: Direction) -> Vec<Gradient> {
+        magnitudes
+            .iter()
+            .map(|&magnitude| Gradient {
+                magnitude,
+                direction,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rounds_directions() {
+        assert_eq!(Direction::new(0, 0), Direction::Horizontal);
+        assert_eq!(Direction::new(-10, 0), Direction::Horizontal);
+        assert_eq!(Direction::new(0, 10), Direction::Vertical);
+        assert_eq!(Direction::new(0, -10), Direction::Vertical);
+        assert_eq!(Direction::new(10, 10), Direction::AntiDiagonal);
+        assert_eq!(Direction::new(-10, -10), Direction::AntiDiagonal);
+        assert_eq!(Direction::new(10, -10), Direction::Diagonal);
+        assert_eq!(Direction::new(-10, 10), Direction::Diagonal);
+
+        // Around tan(22.5°) = 0.414 and tan(67.5°) = 2.414
+        assert_eq!(Direction::new(100, 41), Direction::Horizontal);
+        assert_eq!(Direction::new(100, 42), Direction::AntiDiagonal);
+        assert_eq!(Direction::new(41, 100), Direction::Vertical);
+        assert_eq!(Direction::new(42, 100), Direction::AntiDiagonal);
+    }
+
+    #[test]
+    fn computes_magnitude() {
+        assert_eq!(Gradient::new(3, -4).magnitude, 5.0);
+    }
+
+    #[test]
+    fn keeps_ridge_maxima() {
+        let frame = gradients(&[1.0, 3.0, 5.0, 3.0, 1.0], Direction::Horizontal);
+        let mut out = [-1.0; 5];
+        suppress(&frame, 5, 1, 0..1, &mut out);
+        assert_eq!(out, [0.0, 0.0, 5.0, 0.0, 0.0]);
+
+        // Across the gradient neighbours do not matter
+        let frame = gradients(&[1.0, 3.0, 5.0, 3.0, 1.0], Direction::Vertical);
+        suppress(&frame, 5, 1, 0..1, &mut out);
+        assert_eq!(out, [1.0, 3.0, 5.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn keeps_first_plateau_pixel() {
+        let frame = gradients(&[0.0, 4.0, 4.0, 0.0], Direction::Horizontal);
+        let mut out = [-1.0; 4];
+        suppress(&frame, 4, 1, 0..1, &mut out);
+        assert_eq!(out, [0.0, 4.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn suppresses_stripes_along_diagonals() {
+        #[rustfmt::skip]
+        let magnitudes = [
+            9.0, 0.0, 0.0,
+            0.0, 8.0, 0.0,
+            0.0, 0.0, 9.0,
+        ];
+        let frame = gradients(&magnitudes, Direction::Diagonal);
+        let mut out = [-1.0; 3];
+        suppress(&frame, 3, 3, 1..2, &mut out);
+        assert_eq!(out, [0.0; 3]);
+
+        let frame = gradients(&magnitudes, Direction::AntiDiagonal);
+        suppress(&frame, 3, 3, 1..2, &mut out);
+        assert_eq!(out, [0.0, 8.0, 0.0]);
+    }
+
+    #[test]
+    fn splits_bimodal_magnitudes() {
+        assert_eq!(otsu(&[]), 0.0);
+        assert_eq!(otsu(&[0.0; 4]), 0.0);
+
+        let mut magnitudes = vec![10.0; 100];
+        magnitudes.extend([100.0; 50]);
+        let threshold = otsu(&magnitudes);
+        assert!(10.0 < threshold && threshold < 100.0, "{threshold}");
+
+        // Suppressed pixels are not part of the histogram
+        magnitudes.extend([0.0; 1000]);
+        assert_eq!(otsu(&magnitudes), threshold);
+    }
+
+    #[test]
+    fn traces_weak_edges_from_strong_ones() {
+        let suppressed = [0.0, 5.0, 20.0, 5.0, 0.0, 5.0, 10.0];
+        let mut out = [7; 9];
+        hysteresis(&suppressed, 7, 1, 4.0, 10.0, &mut out, 9);
+        assert_eq!(out, [0, 255, 255, 255, 0, 0, 0, 7, 7]);
+    }
+
+    #[test]
+    fn traces_diagonal_neighbours() {
+        #[rustfmt::skip]
+        let suppressed = [
+            20.0, 0.0, 0.0,
+            0.0, 5.0, 0.0,
+            0.0, 0.0, 5.0,
+            5.0, 0.0, 0.0,
+        ];
+        let mut out = [1; 12];
+        hysteresis(&suppressed, 3, 4, 4.0, 10.0, &mut out, 3);
+        #[rustfmt::skip]
+        let expected = [
+            255, 0, 0,
+            0, 255, 0,
+            0, 0, 255,
+            0, 0, 0,
+        ];
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,401 @@
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+use crate::glib;
+use gst::glib::subclass::prelude::*;
+use gst::glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst_base::subclass::prelude::{BaseTransformImpl, BaseTransformImplExt};
+use gst_base::subclass::BaseTransformMode;
+use gst_video::subclass::prelude::VideoFilterImpl;
+use gst_video::{VideoFormat, VideoFrameExt};
+
+use super::edges::{self, Gradient};
+use crate::cpu_common::border::{BorderMode, RowWindow};
+use crate::cpu_common::filter::Filter;
+use crate::cpu_common::kernel::Kernel;
+use crate::cpu_common::layout::PlaneLayout;
+use crate::cpu_common::operator::Operator;
+use crate::cpu_common::pool::{WorkerPool, MAX_THREADS};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekacpucanny",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's Canny Edge Detector on CPU"),
+    )
+});
+
+/// Formats with 8 bit luma or gray in the first plane
+const SINK_FORMATS: [VideoFormat; 4] = [
+    VideoFormat::Gray8,
+    VideoFormat::I420,
+    VideoFormat::Nv12,
+    VideoFormat::Y444,
+];
+
+const BORDER_MODE: BorderMode = BorderMode::Reflect101;
+/// Low threshold is this fraction of the high one in the automatic mode
+const AUTO_LOW_RATIO: f32 = 0.5;
+
+const DEFAULT_SIGMA: f64 = 1.4;
+const DEFAULT_LOW_THRESHOLD: f64 = 50.0;
+const DEFAULT_HIGH_THRESHOLD: f64 = 150.0;
+const DEFAULT_AUTO_THRESHOLD: bool = false;
+const DEFAULT_N_THREADS: u32 = 0;
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    sigma: f64,
+    low_threshold: f64,
+    high_threshold: f64,
+    auto_threshold: bool,
+    n_threads: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sigma: DEFAULT_SIGMA,
+            low_threshold: DEFAULT_LOW_THRESHOLD,
+            high_threshold: DEFAULT_HIGH_THRESHOLD,
+            auto_threshold: DEFAULT_AUTO_THRESHOLD,
+            n_threads: DEFAULT_N_THREADS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CpuCanny {
+    settings: Mutex<Settings>,
+    pool: Mutex<Option<WorkerPool>>,
+}
+
+impl CpuCanny {
+    /// Computes Sobel gradient of every pixel of the 8 bit `plane`
+    fn gradients(
+        pool: &WorkerPool,
+        plane: &[u8],
+        stride: usize,
+        width: usize,
+        height: usize,
+    ) -> Vec<Gradient> {
+        let kernels = Operator::Sobel3.kernels();
+        let mut gradients = vec![Gradient::default(); width * height];
+
+        pool.for_each_stripe(height, &mut gradients, width, |lines, stripe| {
+            let mut row_window = RowWindow::<u8>::new(BORDER_MODE, 1, 1, 1, 0);
+
+            for line in lines.clone() {
+                let rows = row_window.rows(plane, stride, width, height, line);
+                let out_line = &mut stripe[(line - lines.start) * width..][..width];
+
+                for (col, gradient) in out_line.iter_mut().enumerate() {
+                    let (gx, gy) = kernels.gradient(rows, col, 1);
+                    *gradient = Gradient::new(gx, gy);
+                }
+            }
+        });
+
+        gradients
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for CpuCanny {
+    const NAME: &'static str = "GstCpuCanny";
+    type Type = super::CpuCanny;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            settings: Mutex::new(Settings::default()),
+            pool: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for CpuCanny {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecDouble::builder("sigma")
+                    .nick("Sigma")
+                    .blurb("Standard deviation of the Gaussian pre-blur, 0 = no blur")
+                    .minimum(0.0)
+                    .maximum(2.5)
+                    .default_value(DEFAULT_SIGMA)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("low-threshold")
+                    .nick("Low threshold")
+                    .blurb(
+                        "Gradient magnitude above which pixels connected to strong edges are edges",
+                    )
+                    .minimum(0.0)
+                    .default_value(DEFAULT_LOW_THRESHOLD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("high-threshold")
+                    .nick("High threshold")
+                    .blurb("Gradient magnitude above which pixels are strong edges")
+                    .minimum(0.0)
+                    .default_value(DEFAULT_HIGH_THRESHOLD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoolean::builder("auto-threshold")
+                    .nick("Automatic threshold")
+                    .blurb(
+                        "Compute the high threshold with Otsu's method on every frame and use \
+                         half of it as the low one, ignoring the threshold properties",
+                    )
+                    .default_value(DEFAULT_AUTO_THRESHOLD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("n-threads")
+                    .nick("Number of threads")
+                    .blurb("Number of worker threads processing the frame stripes, 0 = auto")
+                    .maximum(MAX_THREADS)
+                    .default_value(DEFAULT_N_THREADS)
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "sigma" => {
+                let sigma = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing sigma from {} to {}",
+                    settings.sigma,
+                    sigma
+                );
+                settings.sigma = sigma;
+            }
+            "low-threshold" => {
+                let low_threshold = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing low threshold from {} to {}",
+                    settings.low_threshold,
+                    low_threshold
+                );
+                settings.low_threshold = low_threshold;
+            }
+            "high-threshold" => {
+                let high_threshold = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing high threshold from {} to {}",
+                    settings.high_threshold,
+                    high_threshold
+                );
+                settings.high_threshold = high_threshold;
+            }
+            "auto-threshold" => {
+                let auto_threshold = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing automatic threshold from {} to {}",
+                    settings.auto_threshold,
+                    auto_threshold
+                );
+                settings.auto_threshold = auto_threshold;
+            }
+            "n-threads" => {
+                let n_threads = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing number of threads from {} to {}",
+                    settings.n_threads,
+                    n_threads
+                );
+                settings.n_threads = n_threads;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "sigma" => settings.sigma.to_value(),
+            "low-threshold" => settings.low_threshold.to_value(),
+            "high-threshold" => settings.high_threshold.to_value(),
+            "auto-threshold" => settings.auto_threshold.to_value(),
+            "n-threads" => settings.n_threads.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+impl GstObjectImpl for CpuCanny {}
+impl ElementImpl for CpuCanny {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's Canny Edge Detector on CPU",
+                "Filter/Effect/Video",
+                "Detects edges in the luma of the input video frame and outputs them as \
+                 a binary gray frame",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(SINK_FORMATS)
+                .build();
+            let src_caps = gst_video::VideoCapsBuilder::new()
+                .format(VideoFormat::Gray8)
+                .build();
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl BaseTransformImpl for CpuCanny {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        // Size and framerate are kept, only the format changes
+        let formats = if direction == gst::PadDirection::Src {
+            gst::List::new(SINK_FORMATS.map(|format| format.to_str()))
+        } else {
+            gst::List::new([VideoFormat::Gray8.to_str()])
+        };
+
+        let mut other_caps = caps.clone();
+        for s in other_caps.make_mut().iter_mut() {
+            s.set("format", formats.clone());
+            s.remove_fields(["colorimetry", "chroma-site"]);
+        }
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Transformed caps from {} to {} in direction {:?}",
+            caps,
+            other_caps,
+            direction
+        );
+
+        if let Some(filter) = filter {
+            Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
+        } else {
+            Some(other_caps)
+        }
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        // Workers are not needed until the next start
+        *self.pool.lock().unwrap() = None;
+        self.parent_stop()
+    }
+}
+
+impl VideoFilterImpl for CpuCanny {
+    fn transform_frame(
+        &self,
+        inframe: &gst_video::VideoFrameRef<&gst::BufferRef>,
+        outframe: &mut gst_video::VideoFrameRef<&mut gst::BufferRef>,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let start = Instant::now();
+        let settings = *self.settings.lock().unwrap();
+
+        let layout = PlaneLayout::new(inframe.info());
+        let (width, height) = (layout.width, layout.height);
+        let in_stride = inframe.plane_stride()[0] as usize;
+        let out_stride = outframe.plane_stride()[0] as usize;
+
+        let mut pool = self.pool.lock().unwrap();
+        let pool = WorkerPool::get_or_replace(&mut pool, settings.n_threads).map_err(|err| {
+            gst::element_imp_error!(
+                self,
+                gst::ResourceError::Failed,
+                ["Failed to spawn worker threads: {}", err]
+            );
+            gst::FlowError::Error
+        })?;
+
+        let in_plane = inframe.plane_data(0).unwrap();
+
+        let mut blurred = Vec::new();
+        // Sigma 0 turns the smoothing off
+        let (plane, stride) = if let Ok(gaussian) = Kernel::gaussian(settings.sigma) {
+            let filter = Filter::new(gaussian, true, 0.0, 0.0, BORDER_MODE, 0);
+            blurred.resize(width * height, 0);
+            filter.process_plane::<u8>(pool, &layout, in_plane, in_stride, &mut blurred, width);
+            (blurred.as_slice(), width)
+        } else {
+            (in_plane, in_stride)
+        };
+
+        let gradients = Self::gradients(pool, plane, stride, width, height);
+
+        let mut suppressed = vec![0.0; width * height];
+        pool.for_each_stripe(height, &mut suppressed, width, |lines, stripe| {
+            edges::suppress(&gradients, width, height, lines, stripe)
+        });
+
+        let (low, high) = if settings.auto_threshold {
+            let high = edges::otsu(&suppressed);
+            gst::log!(CAT, imp = self, "Otsu threshold {high}");
+            (high * AUTO_LOW_RATIO, high)
+        } else {
+            (
+                settings.low_threshold as f32,
+                settings.high_threshold as f32,
+            )
+        };
+
+        let out_plane = outframe.plane_data_mut(0).unwrap();
+        edges::hysteresis(&suppressed, width, height, low, high, out_plane, out_stride);
+
+        let elapsed = start.elapsed();
+        gst::debug!(
+            CAT,
+            imp = self,
+            "processed in {} ms",
+            1_000.0 * elapsed.as_secs_f64()
+        );
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
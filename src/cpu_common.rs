@@ -0,0 +1,11 @@
+//!
+//! Building blocks shared by the CPU elements and the kernels of the GL elements
+//!
+
+pub(crate) mod border;
+pub(crate) mod filter;
+pub(crate) mod kernel;
+pub(crate) mod layout;
+pub(crate) mod operator;
+pub(crate) mod pool;
+pub(crate) mod sample;
@@ -0,0 +1,291 @@
+//!
+//! Border extrapolation used when the convolution window leaves the frame
+//!
+
+use crate::glib;
+
+use super::sample::Sample;
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstDekaBorderMode")]
+pub enum BorderMode {
+    #[enum_value(name = "Constant: iiiiii|abcdefgh|iiiiiii", nick = "constant")]
+    Constant = 0,
+    #[enum_value(name = "Replicate: aaaaaa|abcdefgh|hhhhhhh", nick = "replicate")]
+    Replicate = 1,
+    #[enum_value(name = "Reflect: fedcba|abcdefgh|hgfedcb", nick = "reflect")]
+    Reflect = 2,
+    #[enum_value(name = "Reflect 101: gfedcb|abcdefgh|gfedcba", nick = "reflect-101")]
+    Reflect101 = 3,
+    #[enum_value(name = "Wrap: cdefgh|abcdefgh|abcdefg", nick = "wrap")]
+    Wrap = 4,
+    #[enum_value(name = "Zero: 000000|abcdefgh|0000000", nick = "zero")]
+    Zero = 5,
+}
+
+impl BorderMode {
+    /// Maps `pos` into `0..len` range, `None` means the border value must be used instead
+    pub fn map(self, pos: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if (0..len).contains(&pos) {
+            return Some(pos as usize);
+        }
+
+        let mapped = match self {
+            BorderMode::Constant | BorderMode::Zero => return None,
+            BorderMode::Replicate => pos.clamp(0, len - 1),
+            BorderMode::Reflect => {
+                let mut pos = pos;
+                while !(0..len).contains(&pos) {
+                    pos = if pos < 0 { -pos - 1 } else { 2 * len - pos - 1 };
+                }
+                pos
+            }
+            BorderMode::Reflect101 if len == 1 => 0,
+            BorderMode::Reflect101 => {
+                let mut pos = pos;
+                while !(0..len).contains(&pos) {
+                    pos = if pos < 0 { -pos } else { 2 * len - pos - 2 };
+                }
+                pos
+            }
+            BorderMode::Wrap => pos.rem_euclid(len),
+        };
+
+        Some(mapped as usize)
+    }
+
+    /// Value used for the pixels outside of the frame, if any
+    pub fn value(self, border_value: u32) -> u32 {
+        match self {
+            BorderMode::Zero => 0,
+            _ => border_value,
+        }
+    }
+}
+
+/// Sliding window of `2 * radius_y + 1` rows of a plane, where each row is extended by `radius_x`
+/// pixels on both sides and rows outside of the plane are extrapolated according to the border mode
+///
+/// Padded rows are cached between calls, so a window must be used for a single frame only
+pub struct RowWindow<T: Sample> {
+    mode: BorderMode,
+    radius_x: usize,
+    radius_y: usize,
+    pixel_stride: usize,
+    value: T,
+    /// Source row of each cached padded row, `None` for the row filled with border value
+    keys: Vec<Option<Option<usize>>>,
+    rows: Vec<Vec<T>>,
+}
+
+impl<T: Sample> RowWindow<T> {
+    /// Creates a window for pixels of `pixel_stride` samples
+    pub fn new(
+        mode: BorderMode,
+        radius_x: usize,
+        radius_y: usize,
+        pixel_stride: usize,
+        border_value: u32,
+    ) -> Self {
+        let size = 2 * radius_y + 1;
+        Self {
+            mode,
+            radius_x,
+            radius_y,
+            pixel_stride,
+            value: T::from_u32_clamped(mode.value(border_value)),
+            keys: vec![None; size],
+            rows: vec![Vec::new(); size],
+        }
+    }
+
+    /// Returns `2 * radius_y + 1` padded rows centered at `line`
+    ///
+    /// `plane` rows are `stride` bytes apart and contain `width` pixels
+    pub fn rows(
+        &mut self,
+        plane: &[u8],
+        stride: usize,
+        width: usize,
+        height: usize,
+        line: usize,
+    ) -> &[Vec<T>] {
+        let first = line as isize - self.radius_y as isize;
+
+        for i in 0..self.rows.len() {
+            let key = self.mode.map(first + i as isize, height);
+            if self.keys[i] == Some(key) {
+                continue;
+            }
+
+            // Rows are usually just shifted by one when moving to the next line, reuse them
+            if let Some(cached) = (i + 1..self.rows.len()).find(|&j| self.keys[j] == Some(key)) {
+                self.rows.swap(i, cached);
+                self.keys.swap(i, cached);
+                continue;
+            }
+
+            let mut row = std::mem::take(&mut self.rows[i]);
+            row.clear();
+            match key {
+                Some(y) => {
+                    let offset = y * stride;
+                    let len = width * self.pixel_stride * T::BYTES;
+                    self.pad(&plane[offset..offset + len], width, &mut row)
+                }
+                None => row.resize((width + 2 * self.radius_x) * self.pixel_stride, self.value),
+            }
+            self.rows[i] = row;
+            self.keys[i] = Some(key);
+        }
+
+        &self.rows
+    }
+
+    fn pad(&self, row: &[u8], width: usize, out: &mut Vec<T>) {
+        let ps = self.pixel_stride;
+        let radius = self.radius_x as isize;
+
+        out.resize(self.radius_x * ps, self.value);
+        T::extend_from_bytes(row, out);
+        out.resize((width + 2 * self.radius_x) * ps, self.value);
+
+        let borders = (-radius..0).chain(width as isize..width as isize + radius);
+        for pos in borders {
+            if let Some(x) = self.mode.map(pos, width) {
+                let src = (x + self.radius_x) * ps;
+                let dst = (pos + radius) as usize * ps;
+                out.copy_within(src..src + ps, dst);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [BorderMode; 6] = [
+        BorderMode::Constant,
+        BorderMode::Replicate,
+        BorderMode::Reflect,
+        BorderMode::Reflect101,
+        BorderMode::Wrap,
+        BorderMode::Zero,
+    ];
+
+    fn map_all(mode: BorderMode, positions: &[isize], len: usize) -> Vec<Option<usize>> {
+        positions.iter().map(|&pos| mode.map(pos, len)).collect()
+    }
+
+    #[test]
+    fn keeps_positions_inside() {
+        for mode in ALL {
+            for pos in 0..8 {
+                assert_eq!(mode.map(pos, 8), Some(pos as usize), "{mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn extrapolates_as_named() {
+        let positions = [-3, -2, -1, 8, 9, 10];
+        let cases = [
+            (BorderMode::Replicate, [0, 0, 0, 7, 7, 7]),
+            (BorderMode::Reflect, [2, 1, 0, 7, 6, 5]),
+            (BorderMode::Reflect101, [3, 2, 1, 6, 5, 4]),
+            (BorderMode::Wrap, [5, 6, 7, 0, 1, 2]),
+        ];
+        for (mode, expected) in cases {
+            let expected: Vec<_> = expected.into_iter().map(Some).collect();
+            assert_eq!(map_all(mode, &positions, 8), expected, "{mode:?}");
+        }
+
+        for mode in [BorderMode::Constant, BorderMode::Zero] {
+            assert_eq!(map_all(mode, &positions, 8), [None; 6], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn reflects_beyond_the_plane_size() {
+        let positions = [-5, -4, 5, 6];
+        assert_eq!(
+            map_all(BorderMode::Reflect, &positions, 2),
+            [Some(0), Some(0), Some(1), Some(1)]
+        );
+        assert_eq!(
+            map_all(BorderMode::Reflect101, &positions, 3),
+            [Some(1), Some(0), Some(1), Some(2)]
+        );
+        assert_eq!(
+            map_all(BorderMode::Wrap, &positions, 3),
+            [Some(1), Some(2), Some(2), Some(0)]
+        );
+    }
+
+    #[test]
+    fn maps_one_pixel_planes() {
+        let positions = [-7, -2, -1, 1, 2, 7];
+        for mode in [
+            BorderMode::Replicate,
+            BorderMode::Reflect,
+            BorderMode::Reflect101,
+            BorderMode::Wrap,
+        ] {
+            assert_eq!(map_all(mode, &positions, 1), [Some(0); 6], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn zero_ignores_border_value() {
+        assert_eq!(BorderMode::Zero.value(7), 0);
+        assert_eq!(BorderMode::Constant.value(7), 7);
+    }
+
+    #[test]
+    fn pads_window_rows() {
+        // 3x2 plane with a padding byte at the end of each line
+        let plane = [1, 2, 3, 0, 4, 5, 6, 0];
+
+        let mut window = RowWindow::<u8>::new(BorderMode::Reflect101, 1, 1, 1, 0);
+        assert_eq!(
+            window.rows(&plane, 4, 3, 2, 0),
+            [[5, 4, 5, 6, 5], [2, 1, 2, 3, 2], [5, 4, 5, 6, 5]]
+        );
+        assert_eq!(
+            window.rows(&plane, 4, 3, 2, 1),
+            [[2, 1, 2, 3, 2], [5, 4, 5, 6, 5], [2, 1, 2, 3, 2]]
+        );
+
+        let mut window = RowWindow::<u8>::new(BorderMode::Constant, 1, 1, 1, 9);
+        assert_eq!(
+            window.rows(&plane, 4, 3, 2, 0),
+            [[9, 9, 9, 9, 9], [9, 1, 2, 3, 9], [9, 4, 5, 6, 9]]
+        );
+    }
+
+    #[test]
+    fn pads_whole_pixels() {
+        // Single line of two pixels with two samples each
+        let plane = [1, 2, 3, 4];
+
+        let mut window = RowWindow::<u8>::new(BorderMode::Replicate, 1, 0, 2, 0);
+        assert_eq!(window.rows(&plane, 4, 2, 1, 0), [[1, 2, 1, 2, 3, 4, 3, 4]]);
+
+        let mut window = RowWindow::<u8>::new(BorderMode::Wrap, 1, 0, 2, 0);
+        assert_eq!(window.rows(&plane, 4, 2, 1, 0), [[3, 4, 1, 2, 3, 4, 1, 2]]);
+    }
+
+    #[test]
+    fn reads_little_endian_samples() {
+        let plane = [0x34, 0x12, 0x78, 0x56];
+
+        let mut window = RowWindow::<u16>::new(BorderMode::Reflect, 1, 0, 1, 0);
+        assert_eq!(
+            window.rows(&plane, 4, 2, 1, 0),
+            [[0x1234, 0x1234, 0x5678, 0x5678]]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//!
+//! Convolution of the first plane with a kernel on the worker pool
+//!
+
+use std::ops::Range;
+
+use super::border::{BorderMode, RowWindow};
+use super::kernel::Kernel;
+use super::layout::PlaneLayout;
+use super::pool::WorkerPool;
+use super::sample::Sample;
+
+/// Kernel prepared for processing a frame
+pub struct Filter {
+    kernel: Kernel,
+    /// Column and row vectors of the kernel if the separable pass is used
+    separated: Option<(Vec<f32>, Vec<f32>)>,
+    divisor: f32,
+    bias: f32,
+    border_mode: BorderMode,
+    border_value: u32,
+}
+
+impl Filter {
+    /// Prepares the `kernel`, zero `divisor` normalizes the kernel unless its coefficients sum
+    /// up to zero. Separable kernels are applied in two passes if `separable` is set
+    pub fn new(
+        kernel: Kernel,
+        separable: bool,
+        divisor: f64,
+        bias: f64,
+        border_mode: BorderMode,
+        border_value: u32,
+    ) -> Self {
+        let separated = separable.then(|| kernel.separate()).flatten();
+
+        let divisor = if divisor != 0.0 {
+            divisor as f32
+        } else if kernel.sum() != 0.0 {
+            kernel.sum()
+        } else {
+            1.0
+        };
+
+        Self {
+            kernel,
+            separated,
+            divisor,
+            bias: bias as f32,
+            border_mode,
+            border_value,
+        }
+    }
+
+    /// Convolves the filtered components in the first plane
+    ///
+    /// The plane is split into horizontal stripes processed in parallel on the `pool`
+    pub fn process_plane<T: Sample>(
+        &self,
+        pool: &WorkerPool,
+        layout: &PlaneLayout,
+        in_plane: &[u8],
+        in_stride: usize,
+        out_plane: &mut [u8],
+        out_stride: usize,
+    ) {
+        pool.for_each_stripe(layout.height, out_plane, out_stride, |lines, out_stripe| {
+            let stripe = Stripe {
+                filter: self,
+                layout,
+                in_plane,
+                in_stride,
+                lines,
+            };
+
+            match &self.separated {
+                Some((column, row)) => {
+                    stripe.process_separable::<T>(column, row, out_stripe, out_stride)
+                }
+                None => stripe.process::<T>(out_stripe, out_stride),
+            }
+        });
+    }
+
+    #[inline(always)]
+    fn store<T: Sample>(&self, acc: f32, out: &mut [u8]) {
+        let value = (acc / self.divisor + self.bias).round().max(0.0);
+        T::from_u32_clamped(value as u32).write(out);
+    }
+}
+
+/// Lines of the input plane processed by a single worker
+struct Stripe<'a> {
+    filter: &'a Filter,
+    layout: &'a PlaneLayout,
+    in_plane: &'a [u8],
+    in_stride: usize,
+    lines: Range<usize>,
+}
+
+impl Stripe<'_> {
+    /// Direct 2D convolution, `out_stripe` starts at the first line of the stripe
+    fn process<T: Sample>(&self, out_stripe: &mut [u8], out_stride: usize) {
+        let layout = self.layout;
+        let kernel = &self.filter.kernel;
+        let ps = layout.pixel_stride;
+
+        let mut row_window = RowWindow::<T>::new(
+            self.filter.border_mode,
+            kernel.radius_x(),
+            kernel.radius_y(),
+            ps,
+            self.filter.border_value,
+        );
+
+        for line in self.lines.clone() {
+            let rows = row_window.rows(
+                self.in_plane,
+                self.in_stride,
+                layout.width,
+                layout.height,
+                line,
+            );
+
+            let in_line = &self.in_plane[self.in_stride * line..];
+            let out_line = &mut out_stripe[out_stride * (line - self.lines.start)..];
+
+            for col in 0..layout.width {
+                for &component in &layout.components {
+                    // Padded rows start `radius_x` pixels to the left of the current one
+                    let first = col * ps + component;
+                    let acc: f32 = rows
+                        .iter()
+                        .enumerate()
+                        .map(|(y, row)| {
+                            kernel
+                                .row(y)
+                                .iter()
+                                .enumerate()
+                                .map(|(x, k)| k * row[first + x * ps].to_i32() as f32)
+                                .sum::<f32>()
+                        })
+                        .sum();
+
+                    self.filter
+                        .store::<T>(acc, &mut out_line[(col * ps + component) * T::BYTES..]);
+                }
+            }
+
+            layout.copy_passthrough::<T>(in_line, out_line);
+        }
+    }
+
+    /// Horizontal pass with the `row` vector followed by vertical pass with the `column` vector,
+    /// `out_stripe` starts at the first line of the stripe
+    fn process_separable<T: Sample>(
+        &self,
+        column: &[f32],
+        row: &[f32],
+        out_stripe: &mut [u8],
+        out_stride: usize,
+    ) {
+        let layout = self.layout;
+        let filter = self.filter;
+        let ps = layout.pixel_stride;
+        let radius_y = column.len() / 2;
+
+        // Only horizontal padding is needed, rows outside of the plane are mapped below
+        let mut row_window = RowWindow::<T>::new(
+            filter.border_mode,
+            row.len() / 2,
+            0,
+            ps,
+            filter.border_value,
+        );
+        let border_value = filter.border_mode.value(filter.border_value).min(T::MAX) as f32;
+
+        // Horizontally filtered rows of the current window, `None` is the row of border value
+        let mut filtered: Vec<(Option<usize>, Vec<f32>)> = Vec::with_capacity(column.len());
+
+        for line in self.lines.clone() {
+            let keys: Vec<Option<usize>> = (0..column.len())
+                .map(|y| {
+                    let pos = (line + y) as isize - radius_y as isize;
+                    filter.border_mode.map(pos, layout.height)
+                })
+                .collect();
+
+            filtered.retain(|(key, _)| keys.contains(key));
+            for &key in &keys {
+                if filtered.iter().any(|(cached, _)| *cached == key) {
+                    continue;
+                }
+
+                let samples = match key {
+                    Some(y) => {
+                        let padded = &row_window.rows(
+                            self.in_plane,
+                            self.in_stride,
+                            layout.width,
+                            layout.height,
+                            y,
+                        )[0];
+                        (0..layout.width * ps)
+                            .map(|i| {
+                                row.iter()
+                                    .enumerate()
+                                    .map(|(x, k)| k * padded[i + x * ps].to_i32() as f32)
+                                    .sum()
+                            })
+                            .collect()
+                    }
+                    None => vec![border_value * row.iter().sum::<f32>(); layout.width * ps],
+                };
+                filtered.push((key, samples));
+            }
+
+            let taps: Vec<&[f32]> = keys
+                .iter()
+                .map(|key| {
+                    filtered
+                        .iter()
+                        .find(|(cached, _)| cached == key)
+                        .map(|(_, samples)| samples.as_slice())
+                        .expect("filtered above")
+                })
+                .collect();
+
+            let in_line = &self.in_plane[self.in_stride * line..];
+            let out_line = &mut out_stripe[out_stride * (line - self.lines.start)..];
+
+            for col in 0..layout.width {
+                for &component in &layout.components {
+                    let pos = col * ps + component;
+                    let acc: f32 = taps.iter().zip(column).map(|(tap, k)| k * tap[pos]).sum();
+
+                    filter.store::<T>(acc, &mut out_line[pos * T::BYTES..]);
+                }
+            }
+
+            layout.copy_passthrough::<T>(in_line, out_line);
+        }
+    }
+}
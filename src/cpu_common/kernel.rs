@@ -0,0 +1,359 @@
+//!
+//! Convolution kernel set through the element property
+//!
+
+use crate::glib;
+use gst::prelude::*;
+
+/// Largest kernel width and height
+pub const MAX_SIZE: usize = 15;
+
+/// `kernel` property taking an array of rows, each of them an array of coefficients
+pub fn param_spec(blurb: &str) -> glib::ParamSpec {
+    gst::ParamSpecArray::builder("kernel")
+        .nick("Kernel")
+        .blurb(blurb)
+        .element_spec(
+            &gst::ParamSpecArray::builder("kernel-row")
+                .nick("Kernel row")
+                .blurb("Coefficients of a kernel row")
+                .element_spec(
+                    &glib::ParamSpecDouble::builder("coefficient")
+                        .nick("Coefficient")
+                        .blurb("Kernel coefficient")
+                        .build(),
+                )
+                .build(),
+        )
+        .mutable_playing()
+        .build()
+}
+
+/// Relative tolerance of checking that a kernel is an outer product of two vectors
+const SEPARABLE_EPSILON: f32 = 1e-5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    /// Row-major coefficients
+    coefficients: Vec<f32>,
+}
+
+impl Default for Kernel {
+    /// 3x3 identity kernel
+    fn default() -> Self {
+        Self {
+            width: 3,
+            height: 3,
+            coefficients: vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl Kernel {
+    pub fn new(
+        width: usize,
+        height: usize,
+        coefficients: Vec<f32>,
+    ) -> Result<Self, glib::BoolError> {
+        let valid_size = |size: usize| size % 2 == 1 && size <= MAX_SIZE;
+        if !valid_size(width) || !valid_size(height) {
+            return Err(glib::bool_error!(
+                "Kernel must have odd width and height up to {MAX_SIZE}, got {width}x{height}"
+            ));
+        }
+        if coefficients.len() != width * height {
+            return Err(glib::bool_error!(
+                "Kernel {width}x{height} needs {} coefficients, got {}",
+                width * height,
+                coefficients.len()
+            ));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            coefficients,
+        })
+    }
+
+    /// Normalized square Gaussian kernel truncated at 3 `sigma` or at the largest size, `sigma`
+    /// must be positive and finite
+    pub fn gaussian(sigma: f64) -> Result<Self, glib::BoolError> {
+        if !(sigma > 0.0 && sigma.is_finite()) {
+            return Err(glib::bool_error!(
+                "Gaussian sigma must be positive, got {sigma}"
+            ));
+        }
+
+        let radius = ((3.0 * sigma).ceil() as usize).clamp(1, MAX_SIZE / 2);
+        let weights: Vec<f32> = (-(radius as isize)..=radius as isize)
+            .map(|x| (-((x * x) as f64) / (2.0 * sigma * sigma)).exp() as f32)
+            .collect();
+        let sum: f32 = weights.iter().sum();
+
+        Ok(Self {
+            width: weights.len(),
+            height: weights.len(),
+            coefficients: weights
+                .iter()
+                .flat_map(|y| weights.iter().map(move |x| x * y / (sum * sum)))
+                .collect(),
+        })
+    }
+
+    /// Parses array of rows, each of them is array of coefficients
+    ///
+    /// The element spec of [`param_spec`] makes GStreamer check that the coefficients are doubles.
+    pub fn from_array(array: &gst::Array) -> Result<Self, glib::BoolError> {
+        let rows = array
+            .iter()
+            .map(|row| {
+                let row = row
+                    .get::<gst::Array>()
+                    .map_err(|_| glib::bool_error!("Kernel row must be an array, got {:?}", row))?;
+                row.iter()
+                    .map(|value| {
+                        value.get::<f64>().map(|x| x as f32).map_err(|_| {
+                            glib::bool_error!(
+                                "Kernel coefficient must be a double, got {:?}",
+                                value
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let width = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(glib::bool_error!("Kernel rows must have the same length"));
+        }
+
+        Self::new(width, rows.len(), rows.concat())
+    }
+
+    /// Parses the value of a property made by [`param_spec`], `size` restricts it to square
+    /// kernels of that size
+    pub fn from_property(
+        value: &glib::Value,
+        size: Option<usize>,
+    ) -> Result<Self, glib::BoolError> {
+        let array = value.get::<gst::Array>().expect("type checked upstream");
+        let kernel = Self::from_array(&array)?;
+
+        match size {
+            Some(size) if kernel.width != size || kernel.height != size => Err(glib::bool_error!(
+                "Kernel must be {size}x{size}, got {}x{}",
+                kernel.width,
+                kernel.height
+            )),
+            _ => Ok(kernel),
+        }
+    }
+
+    /// Array of rows in the format accepted by [`Kernel::from_array`]
+    pub fn to_array(&self) -> gst::Array {
+        gst::Array::new(
+            self.coefficients
+                .chunks(self.width)
+                .map(|row| gst::Array::new(row.iter().map(|&x| x as f64))),
+        )
+    }
+
+    /// Row-major coefficients
+    #[inline]
+    pub fn coefficients(&self) -> &[f32] {
+        &self.coefficients
+    }
+
+    #[inline]
+    pub fn radius_x(&self) -> usize {
+        self.width / 2
+    }
+
+    #[inline]
+    pub fn radius_y(&self) -> usize {
+        self.height / 2
+    }
+
+    #[inline]
+    pub fn row(&self, y: usize) -> &[f32] {
+        &self.coefficients[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn sum(&self) -> f32 {
+        self.coefficients.iter().sum()
+    }
+
+    /// Kernel rotated by 90 degrees clockwise, which turns the vertical gradient of a kernel that
+    /// is positive upwards into the horizontal one that is positive to the right
+    pub fn rotate_clockwise(&self) -> Self {
+        let coefficients = (0..self.width)
+            .flat_map(|y| (0..self.height).map(move |x| (x, y)))
+            .map(|(x, y)| self.row(self.height - 1 - x)[y])
+            .collect();
+
+        Self {
+            width: self.height,
+            height: self.width,
+            coefficients,
+        }
+    }
+
+    /// Splits the kernel into column and row vectors if it is their outer product
+    pub fn separate(&self) -> Option<(Vec<f32>, Vec<f32>)> {
+        let (pivot, &pivot_value) = self
+            .coefficients
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+        if pivot_value == 0.0 {
+            return None;
+        }
+
+        let (pivot_x, pivot_y) = (pivot % self.width, pivot / self.width);
+        let row = self.row(pivot_y).to_vec();
+        let column: Vec<f32> = (0..self.height)
+            .map(|y| self.row(y)[pivot_x] / pivot_value)
+            .collect();
+
+        let tolerance = SEPARABLE_EPSILON * pivot_value.abs();
+        let separable = column.iter().enumerate().all(|(y, c)| {
+            self.row(y)
+                .iter()
+                .zip(&row)
+                .all(|(k, r)| (k - c * r).abs() <= tolerance)
+        });
+
+        separable.then_some((column, row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kernel(width: usize, height: usize, coefficients: &[f32]) -> Kernel {
+        Kernel::new(width, height, coefficients.to_vec()).unwrap()
+    }
+
+    fn outer(column: &[f32], row: &[f32]) -> Vec<f32> {
+        column
+            .iter()
+            .flat_map(|c| row.iter().map(move |r| c * r))
+            .collect()
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        assert!(Kernel::new(2, 3, vec![0.0; 6]).is_err());
+        assert!(Kernel::new(3, 0, vec![]).is_err());
+        assert!(Kernel::new(MAX_SIZE + 2, 1, vec![0.0; MAX_SIZE + 2]).is_err());
+        assert!(Kernel::new(3, 3, vec![0.0; 8]).is_err());
+        assert!(Kernel::new(MAX_SIZE, 1, vec![0.0; MAX_SIZE]).is_ok());
+    }
+
+    #[test]
+    fn separates_outer_products() {
+        let sobel = kernel(3, 3, &outer(&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]));
+        let (column, row) = sobel.separate().unwrap();
+        assert_eq!(outer(&column, &row), sobel.coefficients());
+
+        let box_blur = kernel(5, 3, &[1.0 / 15.0; 15]);
+        let (column, row) = box_blur.separate().unwrap();
+        assert_eq!((column.len(), row.len()), (3, 5));
+        for (k, x) in box_blur.coefficients().iter().zip(outer(&column, &row)) {
+            assert!((k - x).abs() < 1e-6);
+        }
+
+        let (column, row) = Kernel::default().separate().unwrap();
+        assert_eq!(column, [0.0, 1.0, 0.0]);
+        assert_eq!(row, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn keeps_non_separable_kernels_whole() {
+        let laplacian = kernel(3, 3, &[0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(laplacian.separate(), None);
+
+        // Outer product with a single coefficient off by more than the tolerance
+        let mut coefficients = outer(&[1.0, 2.0, 1.0], &[1.0, 2.0, 1.0]);
+        coefficients[0] += 1e-3;
+        assert_eq!(kernel(3, 3, &coefficients).separate(), None);
+
+        assert_eq!(kernel(3, 3, &[0.0; 9]).separate(), None);
+    }
+
+    #[test]
+    fn builds_normalized_gaussian() {
+        let gaussian = Kernel::gaussian(1.0).unwrap();
+        assert_eq!((gaussian.radius_x(), gaussian.radius_y()), (3, 3));
+        assert!((gaussian.sum() - 1.0).abs() < 1e-5);
+
+        let center = gaussian.row(3)[3];
+        assert!(gaussian
+            .coefficients()
+            .iter()
+            .all(|&k| k > 0.0 && k <= center));
+        assert_eq!(gaussian.rotate_clockwise(), gaussian);
+        assert!(gaussian.separate().is_some());
+
+        let small = Kernel::gaussian(0.01).unwrap();
+        assert_eq!((small.radius_x(), small.radius_y()), (1, 1));
+        assert!(small.coefficients().iter().all(|k| k.is_finite()));
+
+        let large = Kernel::gaussian(100.0).unwrap();
+        assert_eq!(large.radius_x(), MAX_SIZE / 2);
+    }
+
+    #[test]
+    fn rejects_invalid_sigma() {
+        for sigma in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Kernel::gaussian(sigma).is_err(), "{sigma}");
+        }
+    }
+
+    #[test]
+    fn rotates_clockwise() {
+        // Vertical gradient positive upwards turns into the horizontal one positive to the right
+        let vertical = kernel(3, 3, &outer(&[1.0, 0.0, -1.0], &[1.0, 2.0, 1.0]));
+        let horizontal = kernel(3, 3, &outer(&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]));
+        assert_eq!(vertical.rotate_clockwise(), horizontal);
+
+        let wide = kernel(3, 1, &[1.0, 2.0, 3.0]);
+        assert_eq!(wide.rotate_clockwise(), kernel(1, 3, &[1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn converts_arrays() {
+        gst::init().unwrap();
+
+        let array = gst::Array::new([
+            gst::Array::new([1.0f64, 2.0, 3.0]),
+            gst::Array::new([4.0f64, 5.0, 6.0]),
+            gst::Array::new([7.0f64, 8.0, 9.0]),
+        ]);
+        let parsed = Kernel::from_array(&array).unwrap();
+        assert_eq!(
+            parsed,
+            kernel(3, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0])
+        );
+        assert_eq!(Kernel::from_array(&parsed.to_array()).unwrap(), parsed);
+
+        let ragged = gst::Array::new([
+            gst::Array::new([1.0f64, 2.0, 3.0]),
+            gst::Array::new([4.0f64]),
+            gst::Array::new([7.0f64, 8.0, 9.0]),
+        ]);
+        assert!(Kernel::from_array(&ragged).is_err());
+
+        let flat = gst::Array::new([1.0f64, 2.0, 3.0]);
+        assert!(Kernel::from_array(&flat).is_err());
+
+        let value = array.to_value();
+        assert!(Kernel::from_property(&value, Some(3)).is_ok());
+        assert!(Kernel::from_property(&value, Some(5)).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+//!
+//! Placement of the samples the CPU filters work with inside of the first plane
+//!
+
+use super::sample::Sample;
+
+/// Largest number of color components in a pixel
+pub const MAX_COMPONENTS: usize = 3;
+
+/// Placement of the filtered components inside of the first plane, all values are in samples
+#[derive(Debug)]
+pub struct PlaneLayout {
+    pub width: usize,
+    pub height: usize,
+    pub pixel_stride: usize,
+    /// Offsets of the color components in the pixel
+    pub components: Vec<usize>,
+    /// Offsets of the rest samples in the pixel (alpha, padding) copied from input as is
+    pub passthrough: Vec<usize>,
+}
+
+impl PlaneLayout {
+    pub fn new(info: &gst_video::VideoInfo) -> Self {
+        let format_info = info.format_info();
+        let sample_bytes = format_info.depth()[0].div_ceil(8) as usize;
+
+        let pixel_stride = format_info.pixel_stride()[0] as usize / sample_bytes;
+
+        // Only color components sharing the first plane are filtered, which is luma for YUV
+        let components: Vec<usize> = (0..format_info.n_components() as usize)
+            .filter(|&c| format_info.plane()[c] == 0)
+            .filter(|&c| {
+                !(format_info.has_alpha() && c == gst_video::ffi::GST_VIDEO_COMP_A as usize)
+            })
+            .map(|c| format_info.poffset()[c] as usize / sample_bytes)
+            .collect();
+        let passthrough = (0..pixel_stride)
+            .filter(|x| !components.contains(x))
+            .collect();
+
+        Self {
+            width: info.width() as usize,
+            height: info.height() as usize,
+            pixel_stride,
+            components,
+            passthrough,
+        }
+    }
+
+    /// Copies samples which are not filtered from `in_line` to `out_line`
+    pub fn copy_passthrough<T: Sample>(&self, in_line: &[u8], out_line: &mut [u8]) {
+        for col in 0..self.width {
+            for &sample in &self.passthrough {
+                let pos = (col * self.pixel_stride + sample) * T::BYTES;
+                out_line[pos..pos + T::BYTES].copy_from_slice(&in_line[pos..pos + T::BYTES]);
+            }
+        }
+    }
+}
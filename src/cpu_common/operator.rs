@@ -0,0 +1,217 @@
+//!
+//! Gradient operators computing horizontal and vertical derivatives
+//!
+
+use crate::glib;
+
+use super::sample::Sample;
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstDekaSobelOperator")]
+pub enum Operator {
+    #[enum_value(name = "Sobel 3x3", nick = "sobel3")]
+    Sobel3 = 0,
+    #[enum_value(name = "Sobel 5x5", nick = "sobel5")]
+    Sobel5 = 1,
+    #[enum_value(name = "Sobel 7x7", nick = "sobel7")]
+    Sobel7 = 2,
+    #[enum_value(name = "Scharr 3x3", nick = "scharr")]
+    Scharr = 3,
+    #[enum_value(name = "Prewitt 3x3", nick = "prewitt")]
+    Prewitt = 4,
+    #[enum_value(name = "Roberts cross 2x2", nick = "roberts")]
+    Roberts = 5,
+}
+
+/// Square kernels of the horizontal and vertical derivative
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Kernels {
+    pub radius: usize,
+    /// Row-major coefficients of the Gx kernel
+    pub x: Vec<i32>,
+    /// Row-major coefficients of the Gy kernel, positive at the top
+    pub y: Vec<i32>,
+}
+
+impl Kernels {
+    /// Sum of positive Gx coefficients, the largest response is `norm * T::MAX`
+    pub fn norm(&self) -> i32 {
+        self.x.iter().filter(|&&k| k > 0).sum()
+    }
+
+    /// Returns `(gx, gy)` of the window starting at `first` in the rows padded by the radius
+    #[inline(always)]
+    pub fn gradient<T: Sample>(&self, rows: &[Vec<T>], first: usize, ps: usize) -> (i32, i32) {
+        (
+            convolve(rows, first, ps, &self.x),
+            convolve(rows, first, ps, &self.y),
+        )
+    }
+}
+
+impl Operator {
+    pub fn kernels(self) -> Kernels {
+        match self {
+            Operator::Sobel3 => separable(&[1, 2, 1], &[-1, 0, 1]),
+            Operator::Sobel5 => separable(&[1, 4, 6, 4, 1], &[-1, -2, 0, 2, 1]),
+            Operator::Sobel7 => separable(&[1, 6, 15, 20, 15, 6, 1], &[-1, -4, -5, 0, 5, 4, 1]),
+            Operator::Scharr => separable(&[3, 10, 3], &[-1, 0, 1]),
+            Operator::Prewitt => separable(&[1, 1, 1], &[-1, 0, 1]),
+            // Diagonal differences of the 2x2 window starting at the center
+            Operator::Roberts => Kernels {
+                radius: 1,
+                x: vec![
+                    0, 0, 0, //
+                    0, 1, 0, //
+                    0, 0, -1, //
+                ],
+                y: vec![
+                    0, 0, 0, //
+                    0, 0, 1, //
+                    0, -1, 0, //
+                ],
+            },
+        }
+    }
+
+    /// Weights `[a, b]` of 3x3 operators with `[a, b, a]` smoothing, which have vector kernels
+    pub fn smoothing_3x3(self) -> Option<[i32; 2]> {
+        match self {
+            Operator::Sobel3 => Some([1, 2]),
+            Operator::Scharr => Some([3, 10]),
+            Operator::Prewitt => Some([1, 1]),
+            Operator::Sobel5 | Operator::Sobel7 | Operator::Roberts => None,
+        }
+    }
+}
+
+/// Returns signed response to the square row-major `kernel` of the window starting at `first`
+///
+/// Responses of 7x7 Sobel to 16 bit samples are within ±84M, so i32 is wide enough
+#[inline(always)]
+fn convolve<T: Sample>(rows: &[Vec<T>], first: usize, ps: usize, kernel: &[i32]) -> i32 {
+    rows.iter()
+        .zip(kernel.chunks_exact(rows.len()))
+        .map(|(row, k)| {
+            k.iter()
+                .enumerate()
+                .map(|(x, &k)| row[first + x * ps].to_i32() * k)
+                .sum::<i32>()
+        })
+        .sum()
+}
+
+/// Gx is outer product of `smoothing` column and `derivative` row, Gy is its negated transpose
+fn separable(smoothing: &[i32], derivative: &[i32]) -> Kernels {
+    Kernels {
+        radius: smoothing.len() / 2,
+        x: smoothing
+            .iter()
+            .flat_map(|s| derivative.iter().map(move |d| s * d))
+            .collect(),
+        y: derivative
+            .iter()
+            .flat_map(|d| smoothing.iter().map(move |s| -d * s))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Operator; 6] = [
+        Operator::Sobel3,
+        Operator::Sobel5,
+        Operator::Sobel7,
+        Operator::Scharr,
+        Operator::Prewitt,
+        Operator::Roberts,
+    ];
+
+    /// Rows of `size` samples padded by `radius` on each side, `value(x, y)` gives the samples
+    fn padded_rows(radius: usize, value: impl Fn(usize, usize) -> u8) -> Vec<Vec<u8>> {
+        let size = 2 * radius + 1;
+        (0..size)
+            .map(|y| (0..size).map(|x| value(x, y)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn builds_sobel_3x3() {
+        let kernels = Operator::Sobel3.kernels();
+        assert_eq!(kernels.radius, 1);
+        assert_eq!(kernels.x, [-1, 0, 1, -2, 0, 2, -1, 0, 1]);
+        assert_eq!(kernels.y, [1, 2, 1, 0, 0, 0, -1, -2, -1]);
+        assert_eq!(kernels.norm(), 4);
+    }
+
+    #[test]
+    fn builds_square_zero_sum_kernels() {
+        for operator in ALL {
+            let kernels = operator.kernels();
+            let size = 2 * kernels.radius + 1;
+            assert_eq!(kernels.x.len(), size * size, "{operator:?}");
+            assert_eq!(kernels.y.len(), size * size, "{operator:?}");
+            assert_eq!(kernels.x.iter().sum::<i32>(), 0, "{operator:?}");
+            assert_eq!(kernels.y.iter().sum::<i32>(), 0, "{operator:?}");
+        }
+    }
+
+    #[test]
+    fn responds_to_ramps() {
+        for operator in ALL.into_iter().filter(|&op| op != Operator::Roberts) {
+            let kernels = operator.kernels();
+            let radius = kernels.radius;
+
+            let right = padded_rows(radius, |x, _| 10 * x as u8);
+            let (gx, gy) = kernels.gradient(&right, 0, 1);
+            assert!(gx > 0 && gy == 0, "{operator:?}: {gx} {gy}");
+
+            let up = padded_rows(radius, |_, y| 100 - 10 * y as u8);
+            let (gx, gy) = kernels.gradient(&up, 0, 1);
+            assert!(gx == 0 && gy > 0, "{operator:?}: {gx} {gy}");
+        }
+    }
+
+    #[test]
+    fn differences_roberts_diagonals() {
+        let kernels = Operator::Roberts.kernels();
+        let rows = padded_rows(1, |x, y| [[0, 0, 0], [0, 5, 7], [0, 11, 2]][y][x]);
+        assert_eq!(kernels.gradient(&rows, 0, 1), (5 - 2, 7 - 11));
+    }
+
+    #[test]
+    fn matches_vector_weights() {
+        for operator in ALL {
+            let Some([a, b]) = operator.smoothing_3x3() else {
+                continue;
+            };
+            assert_eq!(operator.kernels(), separable(&[a, b, a], &[-1, 0, 1]));
+        }
+    }
+
+    #[test]
+    fn fits_16_bit_responses() {
+        for operator in ALL {
+            let kernels = operator.kernels();
+            for kernel in [&kernels.x, &kernels.y] {
+                let largest =
+                    kernel.iter().map(|k| k.unsigned_abs() as u64).sum::<u64>() * u16::MAX as u64;
+                assert!(largest <= i32::MAX as u64, "{operator:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn skips_interleaved_samples() {
+        // Two samples per pixel, only the second one has a gradient
+        let kernels = Operator::Prewitt.kernels();
+        let rows: Vec<Vec<u8>> = (0..3)
+            .map(|_| (0..3).flat_map(|x| [50, 10 * x as u8]).collect())
+            .collect();
+        assert_eq!(kernels.gradient(&rows, 0, 2), (0, 0));
+        assert_eq!(kernels.gradient(&rows, 1, 2), (60, 0));
+    }
+}
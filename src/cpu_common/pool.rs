@@ -0,0 +1,197 @@
+//!
+//! Persistent worker threads for splitting frame processing into stripes
+//!
+
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Upper bound of the `n-threads` properties, more workers than this only cost memory
+pub const MAX_THREADS: u32 = 256;
+
+/// Fixed set of threads living as long as the pool, jobs are submitted with [`WorkerPool::scope`]
+#[derive(Debug)]
+pub struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Spawns `n_threads` workers, the ones already running are stopped again when spawning fails
+    pub fn new(n_threads: usize) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut pool = Self {
+            sender: Some(sender),
+            threads: Vec::with_capacity(n_threads.max(1)),
+        };
+        for i in 0..n_threads.max(1) {
+            let receiver = receiver.clone();
+            let thread = thread::Builder::new()
+                .name(format!("deka-worker-{i}"))
+                .spawn(move || loop {
+                    // Lock is released before running the job, so other workers can pick the next one
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })?;
+            pool.threads.push(thread);
+        }
+
+        Ok(pool)
+    }
+
+    /// Returns the pool in `slot` with `n_threads` workers (0 = one per CPU), the pool is replaced
+    /// when the number of workers changes
+    ///
+    /// At most [`MAX_THREADS`] workers are spawned.
+    pub fn get_or_replace(
+        slot: &mut Option<WorkerPool>,
+        n_threads: u32,
+    ) -> io::Result<&WorkerPool> {
+        let n_threads = match n_threads {
+            0 => thread::available_parallelism().map_or(1, |x| x.get()),
+            n => n as usize,
+        }
+        .min(MAX_THREADS as usize);
+
+        match slot.take() {
+            Some(pool) if pool.n_threads() == n_threads => Ok(slot.insert(pool)),
+            _ => Ok(slot.insert(WorkerPool::new(n_threads)?)),
+        }
+    }
+
+    #[inline]
+    pub fn n_threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Splits `out_plane` of `height` rows `out_stride` elements apart into a stripe per worker
+    /// and runs `f` with lines of each stripe in parallel
+    pub fn for_each_stripe<T, F>(&self, height: usize, out_plane: &mut [T], out_stride: usize, f: F)
+    where
+        T: Send,
+        F: Fn(Range<usize>, &mut [T]) + Sync,
+    {
+        let stripe_height = height.div_ceil(self.n_threads()).max(1);
+        let f = &f;
+
+        self.scope(|s| {
+            for (i, out_stripe) in out_plane.chunks_mut(stripe_height * out_stride).enumerate() {
+                let first = i * stripe_height;
+                let lines = first..(first + stripe_height).min(height);
+                if lines.is_empty() {
+                    break;
+                }
+
+                s.spawn(move || f(lines, out_stripe));
+            }
+        });
+    }
+
+    /// Runs `f` which may spawn jobs borrowing data from the caller, returns after all spawned jobs finished
+    ///
+    /// # Panics
+    /// Panics if any of the spawned jobs panicked
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        let scope = Scope {
+            sender: self.sender.as_ref().expect("pool is alive"),
+            pending: Arc::new(Pending::default()),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        // Jobs may borrow from the caller, so we must wait for them even if `f` panicked
+        scope.pending.wait();
+
+        match result {
+            Err(err) => panic::resume_unwind(err),
+            Ok(_) if scope.pending.panicked.load(Ordering::Relaxed) => {
+                panic!("a worker job panicked")
+            }
+            Ok(result) => result,
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers
+        self.sender.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    done: Condvar,
+    panicked: AtomicBool,
+}
+
+impl Pending {
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.done.wait(count).unwrap();
+        }
+    }
+
+    fn finish(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.done.notify_all();
+        }
+    }
+}
+
+/// Handle for spawning jobs inside of [`WorkerPool::scope`]
+pub struct Scope<'scope, 'env: 'scope> {
+    sender: &'scope mpsc::Sender<Job>,
+    pending: Arc<Pending>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope> Scope<'scope, '_> {
+    pub fn spawn<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        *self.pending.count.lock().unwrap() += 1;
+
+        let pending = self.pending.clone();
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                pending.panicked.store(true, Ordering::Relaxed);
+            }
+            pending.finish();
+        });
+
+        // SAFETY: `WorkerPool::scope` does not return until all spawned jobs are finished,
+        // so everything borrowed for `'scope` outlives the job
+        let job: Job = unsafe { std::mem::transmute(job) };
+
+        if let Err(mpsc::SendError(job)) = self.sender.send(job) {
+            // Workers are gone, run the job here to keep the accounting right
+            job();
+        }
+    }
+}
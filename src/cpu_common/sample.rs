@@ -0,0 +1,82 @@
+//!
+//! Storage types of the video components the CPU filters work with
+//!
+
+/// Single component value of a pixel as stored in the plane
+pub trait Sample: Copy + Default + Send + Sync + 'static {
+    /// Size of the sample in the plane
+    const BYTES: usize;
+    /// Largest value a sample can hold
+    const MAX: u32;
+
+    fn to_i32(self) -> i32;
+
+    fn from_u32_clamped(value: u32) -> Self;
+
+    /// Appends samples stored in little endian `bytes` to `out`
+    fn extend_from_bytes(bytes: &[u8], out: &mut Vec<Self>);
+
+    /// Stores sample into the first `BYTES` of `out` in little endian
+    fn write(self, out: &mut [u8]);
+
+    /// Views samples as bytes, if they are single byte
+    fn as_bytes(_samples: &[Self]) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl Sample for u8 {
+    const BYTES: usize = 1;
+    const MAX: u32 = u8::MAX as u32;
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+
+    #[inline(always)]
+    fn from_u32_clamped(value: u32) -> Self {
+        value.min(<Self as Sample>::MAX) as u8
+    }
+
+    fn extend_from_bytes(bytes: &[u8], out: &mut Vec<Self>) {
+        out.extend_from_slice(bytes);
+    }
+
+    #[inline(always)]
+    fn write(self, out: &mut [u8]) {
+        out[0] = self;
+    }
+
+    fn as_bytes(samples: &[Self]) -> Option<&[u8]> {
+        Some(samples)
+    }
+}
+
+impl Sample for u16 {
+    const BYTES: usize = 2;
+    const MAX: u32 = u16::MAX as u32;
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+
+    #[inline(always)]
+    fn from_u32_clamped(value: u32) -> Self {
+        value.min(<Self as Sample>::MAX) as u16
+    }
+
+    fn extend_from_bytes(bytes: &[u8], out: &mut Vec<Self>) {
+        out.extend(
+            bytes
+                .chunks_exact(Self::BYTES)
+                .map(|x| u16::from_le_bytes([x[0], x[1]])),
+        );
+    }
+
+    #[inline(always)]
+    fn write(self, out: &mut [u8]) {
+        out[..Self::BYTES].copy_from_slice(&self.to_le_bytes());
+    }
+}
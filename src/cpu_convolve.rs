@@ -0,0 +1,17 @@
+mod imp;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+    pub struct CpuConvolve(ObjectSubclass<imp::CpuConvolve>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekacpuconvolve",
+        gst::Rank::NONE,
+        CpuConvolve::static_type(),
+    )
+}
@@ -0,0 +1,386 @@
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+use crate::glib;
+use gst::glib::subclass::prelude::*;
+use gst::glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst_base::subclass::prelude::{BaseTransformImpl, BaseTransformImplExt};
+use gst_base::subclass::BaseTransformMode;
+use gst_video::subclass::prelude::VideoFilterImpl;
+use gst_video::VideoFrameExt;
+
+use crate::cpu_common::border::BorderMode;
+use crate::cpu_common::filter::Filter;
+use crate::cpu_common::kernel::{self, Kernel};
+use crate::cpu_common::layout::PlaneLayout;
+use crate::cpu_common::pool::{WorkerPool, MAX_THREADS};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekacpuconvolve",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's Convolution Filter on CPU"),
+    )
+});
+
+const DEFAULT_DIVISOR: f64 = 0.0;
+const DEFAULT_BIAS: f64 = 0.0;
+const DEFAULT_SEPARABLE: bool = false;
+const DEFAULT_BORDER_MODE: BorderMode = BorderMode::Reflect101;
+const DEFAULT_BORDER_VALUE: u32 = 0;
+const DEFAULT_N_THREADS: u32 = 0;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    kernel: Kernel,
+    divisor: f64,
+    bias: f64,
+    separable: bool,
+    border_mode: BorderMode,
+    border_value: u32,
+    n_threads: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            kernel: Kernel::default(),
+            divisor: DEFAULT_DIVISOR,
+            bias: DEFAULT_BIAS,
+            separable: DEFAULT_SEPARABLE,
+            border_mode: DEFAULT_BORDER_MODE,
+            border_value: DEFAULT_BORDER_VALUE,
+            n_threads: DEFAULT_N_THREADS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CpuConvolve {
+    settings: Mutex<Settings>,
+    pool: Mutex<Option<WorkerPool>>,
+}
+
+impl CpuConvolve {
+    fn warn_if_not_separable(&self, settings: &Settings) {
+        if settings.separable && settings.kernel.separate().is_none() {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Kernel is not separable, full 2D convolution will be used"
+            );
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for CpuConvolve {
+    const NAME: &'static str = "GstCpuConvolve";
+    type Type = super::CpuConvolve;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            settings: Mutex::new(Settings::default()),
+            pool: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for CpuConvolve {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                kernel::param_spec(
+                    "Array of kernel rows, width and height must be odd and not larger than 15",
+                ),
+                glib::ParamSpecDouble::builder("divisor")
+                    .nick("Divisor")
+                    .blurb(
+                        "Value the weighted sum is divided by, 0 = sum of the kernel coefficients",
+                    )
+                    .default_value(DEFAULT_DIVISOR)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("bias")
+                    .nick("Bias")
+                    .blurb("Value added to the result after division")
+                    .default_value(DEFAULT_BIAS)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoolean::builder("separable")
+                    .nick("Separable")
+                    .blurb(
+                        "Use horizontal and vertical passes if the kernel is an outer product \
+                         of two vectors",
+                    )
+                    .default_value(DEFAULT_SEPARABLE)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("border-mode", DEFAULT_BORDER_MODE)
+                    .nick("Border mode")
+                    .blurb("How pixels outside of the frame are extrapolated")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("border-value")
+                    .nick("Border value")
+                    .blurb("Value of the pixels outside of the frame for the constant border mode")
+                    .maximum(u16::MAX as u32)
+                    .default_value(DEFAULT_BORDER_VALUE)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("n-threads")
+                    .nick("Number of threads")
+                    .blurb("Number of worker threads processing the frame stripes, 0 = auto")
+                    .maximum(MAX_THREADS)
+                    .default_value(DEFAULT_N_THREADS)
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "kernel" => match Kernel::from_property(value, None) {
+                Ok(kernel) => {
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing kernel from {:?} to {:?}",
+                        settings.kernel,
+                        kernel
+                    );
+                    settings.kernel = kernel;
+                    self.warn_if_not_separable(&settings);
+                }
+                Err(err) => {
+                    gst::error!(CAT, imp = self, "Invalid kernel {:?}: {}", value, err);
+                }
+            },
+            "divisor" => {
+                let divisor = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing divisor from {} to {}",
+                    settings.divisor,
+                    divisor
+                );
+                settings.divisor = divisor;
+            }
+            "bias" => {
+                let bias = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing bias from {} to {}",
+                    settings.bias,
+                    bias
+                );
+                settings.bias = bias;
+            }
+            "separable" => {
+                let separable = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing separable from {} to {}",
+                    settings.separable,
+                    separable
+                );
+                settings.separable = separable;
+                self.warn_if_not_separable(&settings);
+            }
+            "border-mode" => {
+                let border_mode = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing border mode from {:?} to {:?}",
+                    settings.border_mode,
+                    border_mode
+                );
+                settings.border_mode = border_mode;
+            }
+            "border-value" => {
+                let border_value = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing border value from {} to {}",
+                    settings.border_value,
+                    border_value
+                );
+                settings.border_value = border_value;
+            }
+            "n-threads" => {
+                let n_threads = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing number of threads from {} to {}",
+                    settings.n_threads,
+                    n_threads
+                );
+                settings.n_threads = n_threads;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "kernel" => settings.kernel.to_array().to_value(),
+            "divisor" => settings.divisor.to_value(),
+            "bias" => settings.bias.to_value(),
+            "separable" => settings.separable.to_value(),
+            "border-mode" => settings.border_mode.to_value(),
+            "border-value" => settings.border_value.to_value(),
+            "n-threads" => settings.n_threads.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+impl GstObjectImpl for CpuConvolve {}
+impl ElementImpl for CpuConvolve {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's Convolution Filter on CPU",
+                "Filter/Effect/Video",
+                "Applies an arbitrary convolution kernel to the color components or luma of \
+                 the input video frame",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst_video::VideoCapsBuilder::new()
+                .format_list([
+                    gst_video::VideoFormat::Rgbx,
+                    gst_video::VideoFormat::Bgrx,
+                    gst_video::VideoFormat::Xrgb,
+                    gst_video::VideoFormat::Xbgr,
+                    gst_video::VideoFormat::Rgba,
+                    gst_video::VideoFormat::Bgra,
+                    gst_video::VideoFormat::Argb,
+                    gst_video::VideoFormat::Abgr,
+                    gst_video::VideoFormat::Rgb,
+                    gst_video::VideoFormat::Bgr,
+                    gst_video::VideoFormat::Gray8,
+                    gst_video::VideoFormat::Gray16Le,
+                    gst_video::VideoFormat::I420,
+                    gst_video::VideoFormat::Nv12,
+                    gst_video::VideoFormat::Y444,
+                ])
+                .build();
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl BaseTransformImpl for CpuConvolve {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        // Workers are not needed until the next start
+        *self.pool.lock().unwrap() = None;
+        self.parent_stop()
+    }
+}
+
+impl VideoFilterImpl for CpuConvolve {
+    fn transform_frame(
+        &self,
+        inframe: &gst_video::VideoFrameRef<&gst::BufferRef>,
+        outframe: &mut gst_video::VideoFrameRef<&mut gst::BufferRef>,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let start = Instant::now();
+        let (filter, n_threads) = {
+            let settings = self.settings.lock().unwrap();
+            let filter = Filter::new(
+                settings.kernel.clone(),
+                settings.separable,
+                settings.divisor,
+                settings.bias,
+                settings.border_mode,
+                settings.border_value,
+            );
+            (filter, settings.n_threads)
+        };
+
+        let layout = PlaneLayout::new(inframe.info());
+        let in_stride = inframe.plane_stride()[0] as usize;
+        let out_stride = outframe.plane_stride()[0] as usize;
+        let format_info = inframe.format_info();
+
+        let mut pool = self.pool.lock().unwrap();
+        let pool = WorkerPool::get_or_replace(&mut pool, n_threads).map_err(|err| {
+            gst::element_imp_error!(
+                self,
+                gst::ResourceError::Failed,
+                ["Failed to spawn worker threads: {}", err]
+            );
+            gst::FlowError::Error
+        })?;
+
+        let in_plane = inframe.plane_data(0).unwrap();
+        let out_plane = outframe.plane_data_mut(0).unwrap();
+
+        match format_info.depth()[0] {
+            8 => filter
+                .process_plane::<u8>(pool, &layout, in_plane, in_stride, out_plane, out_stride),
+            16 => filter
+                .process_plane::<u16>(pool, &layout, in_plane, in_stride, out_plane, out_stride),
+            depth => {
+                gst::error!(CAT, imp = self, "Unsupported component depth {depth}");
+                return Err(gst::FlowError::NotSupported);
+            }
+        }
+
+        // Only luma is filtered for YUV, chroma is kept as is
+        for plane in 1..outframe.n_planes() {
+            if let Err(err) = inframe.copy_plane(outframe, plane) {
+                gst::error!(CAT, imp = self, "Failed to copy plane {plane}: {err}");
+                return Err(gst::FlowError::Error);
+            }
+        }
+
+        let elapsed = start.elapsed();
+        gst::debug!(
+            CAT,
+            imp = self,
+            "processed in {} ms",
+            1_000.0 * elapsed.as_secs_f64()
+        );
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
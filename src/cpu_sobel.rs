@@ -1,4 +1,6 @@
 mod imp;
+mod output;
+mod simd;
 
 use gst::glib;
 use gst::prelude::*;
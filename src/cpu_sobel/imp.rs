@@ -1,15 +1,25 @@
-use std::sync::LazyLock;
+use std::ops::Range;
+use std::sync::{LazyLock, Mutex};
 use std::time::Instant;
 
 use crate::glib;
 use gst::glib::subclass::prelude::*;
 use gst::glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
+use gst::prelude::*;
 use gst::subclass::prelude::*;
-use gst_base::subclass::prelude::BaseTransformImpl;
+use gst_base::subclass::prelude::{BaseTransformImpl, BaseTransformImplExt};
 use gst_base::subclass::BaseTransformMode;
 use gst_video::subclass::prelude::VideoFilterImpl;
 use gst_video::VideoFrameExt;
 
+use super::output::OutputMode;
+use super::simd::SimdKernel;
+use crate::cpu_common::border::{BorderMode, RowWindow};
+use crate::cpu_common::layout::{PlaneLayout, MAX_COMPONENTS};
+use crate::cpu_common::operator::Operator;
+use crate::cpu_common::pool::{WorkerPool, MAX_THREADS};
+use crate::cpu_common::sample::Sample;
+
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
         "dekacpusobel",
@@ -18,10 +28,170 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     )
 });
 
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstDekaSobelMagnitude")]
+pub enum Magnitude {
+    #[enum_value(name = "L1: |Gx| + |Gy|", nick = "l1")]
+    L1 = 0,
+    #[enum_value(name = "L2: sqrt(Gx^2 + Gy^2)", nick = "l2")]
+    L2 = 1,
+    #[enum_value(name = "Max: max(|Gx|, |Gy|)", nick = "max")]
+    Max = 2,
+}
+
+impl Magnitude {
+    /// Combines horizontal and vertical responses of single channel into the gradient magnitude
+    #[inline(always)]
+    pub(super) fn combine(self, gx: i32, gy: i32) -> u32 {
+        match self {
+            Magnitude::L1 => gx.unsigned_abs() + gy.unsigned_abs(),
+            Magnitude::L2 => {
+                // Squares of the large kernel responses do not fit into i32
+                let squares = (gx as i64).pow(2) + (gy as i64).pow(2);
+                (squares as f32).sqrt() as u32
+            }
+            Magnitude::Max => gx.unsigned_abs().max(gy.unsigned_abs()),
+        }
+    }
+}
+
+const DEFAULT_OPERATOR: Operator = Operator::Sobel3;
+const DEFAULT_MAGNITUDE: Magnitude = Magnitude::L1;
+const DEFAULT_BORDER_MODE: BorderMode = BorderMode::Reflect101;
+const DEFAULT_BORDER_VALUE: u32 = 0;
+const DEFAULT_N_THREADS: u32 = 0;
+const DEFAULT_SIMD: bool = true;
+const DEFAULT_OUTPUT_MODE: OutputMode = OutputMode::Channels;
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    operator: Operator,
+    magnitude: Magnitude,
+    border_mode: BorderMode,
+    border_value: u32,
+    n_threads: u32,
+    simd: bool,
+    output_mode: OutputMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            operator: DEFAULT_OPERATOR,
+            magnitude: DEFAULT_MAGNITUDE,
+            border_mode: DEFAULT_BORDER_MODE,
+            border_value: DEFAULT_BORDER_VALUE,
+            n_threads: DEFAULT_N_THREADS,
+            simd: DEFAULT_SIMD,
+            output_mode: DEFAULT_OUTPUT_MODE,
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct CpuSobel {}
+pub struct CpuSobel {
+    settings: Mutex<Settings>,
+    pool: Mutex<Option<WorkerPool>>,
+}
 
-impl CpuSobel {}
+impl CpuSobel {
+    /// Computes gradient magnitude of the filtered components in the first plane
+    ///
+    /// The plane is split into horizontal stripes processed in parallel on the `pool`
+    fn process_plane<T: Sample>(
+        pool: &WorkerPool,
+        settings: &Settings,
+        layout: &PlaneLayout,
+        in_plane: &[u8],
+        in_stride: usize,
+        out_plane: &mut [u8],
+        out_stride: usize,
+    ) {
+        pool.for_each_stripe(layout.height, out_plane, out_stride, |lines, out_stripe| {
+            Self::process_stripe::<T>(
+                settings, layout, in_plane, in_stride, lines, out_stripe, out_stride,
+            )
+        });
+    }
+
+    /// Processes `lines` of the plane, `out_stripe` starts at the first of them
+    fn process_stripe<T: Sample>(
+        settings: &Settings,
+        layout: &PlaneLayout,
+        in_plane: &[u8],
+        in_stride: usize,
+        lines: Range<usize>,
+        out_stripe: &mut [u8],
+        out_stride: usize,
+    ) {
+        let ps = layout.pixel_stride;
+        let kernels = settings.operator.kernels();
+        let norm = kernels.norm();
+
+        // Vector kernels only compute magnitude of each channel for 3x3 operators
+        let simd = SimdKernel::detected()
+            .filter(|_| settings.simd && settings.output_mode == OutputMode::Channels)
+            .zip(settings.operator.smoothing_3x3());
+
+        // Rows are extended by the kernel radius on each side, so every output pixel has full window
+        let mut row_window = RowWindow::<T>::new(
+            settings.border_mode,
+            kernels.radius,
+            kernels.radius,
+            ps,
+            settings.border_value,
+        );
+
+        for line in lines.clone() {
+            let rows = row_window.rows(in_plane, in_stride, layout.width, layout.height, line);
+
+            let in_line = &in_plane[in_stride * line..];
+            let out_line = &mut out_stripe[out_stride * (line - lines.start)..];
+
+            let byte_rows = match rows {
+                [prev, current, next] => {
+                    (T::as_bytes(prev), T::as_bytes(current), T::as_bytes(next))
+                }
+                _ => (None, None, None),
+            };
+
+            if let (Some((simd, weights)), (Some(prev), Some(current), Some(next))) =
+                (simd, byte_rows)
+            {
+                // Vector kernel filters every sample, alpha and padding are restored below
+                let out_row = &mut out_line[..layout.width * ps];
+                simd.sobel_row(
+                    [prev, current, next],
+                    ps,
+                    weights,
+                    settings.magnitude,
+                    out_row,
+                );
+            } else {
+                let mut gradients = [(0, 0); MAX_COMPONENTS];
+                let gradients = &mut gradients[..layout.components.len()];
+
+                for col in 0..layout.width {
+                    for (gradient, &component) in gradients.iter_mut().zip(&layout.components) {
+                        // Padded rows start `radius` pixels to the left of the current one
+                        *gradient = kernels.gradient(rows, col * ps + component, ps);
+                    }
+
+                    settings.output_mode.write_pixel::<T>(
+                        settings.magnitude,
+                        norm,
+                        gradients,
+                        &layout.components,
+                        &mut out_line[col * ps * T::BYTES..],
+                    );
+                }
+            }
+
+            layout.copy_passthrough::<T>(in_line, out_line);
+        }
+    }
+}
 
 #[glib::object_subclass]
 impl ObjectSubclass for CpuSobel {
@@ -30,11 +200,160 @@ impl ObjectSubclass for CpuSobel {
     type ParentType = gst_video::VideoFilter;
 
     fn with_class(_klass: &Self::Class) -> Self {
-        Self {}
+        Self {
+            settings: Mutex::new(Settings::default()),
+            pool: Mutex::new(None),
+        }
     }
 }
 
-impl ObjectImpl for CpuSobel {}
+impl ObjectImpl for CpuSobel {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecEnum::builder_with_default("operator", DEFAULT_OPERATOR)
+                    .nick("Operator")
+                    .blurb("Kernels computing horizontal and vertical gradients")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("magnitude", DEFAULT_MAGNITUDE)
+                    .nick("Magnitude")
+                    .blurb("How horizontal and vertical gradients are combined")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("border-mode", DEFAULT_BORDER_MODE)
+                    .nick("Border mode")
+                    .blurb("How pixels outside of the frame are extrapolated")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("border-value")
+                    .nick("Border value")
+                    .blurb("Value of the pixels outside of the frame for the constant border mode")
+                    .maximum(u16::MAX as u32)
+                    .default_value(DEFAULT_BORDER_VALUE)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("n-threads")
+                    .nick("Number of threads")
+                    .blurb("Number of worker threads processing the frame stripes, 0 = auto")
+                    .maximum(MAX_THREADS)
+                    .default_value(DEFAULT_N_THREADS)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoolean::builder("simd")
+                    .nick("SIMD")
+                    .blurb("Use vectorized kernels if the CPU supports them, scalar code otherwise")
+                    .default_value(DEFAULT_SIMD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("output-mode", DEFAULT_OUTPUT_MODE)
+                    .nick("Output mode")
+                    .blurb("How the gradient is visualized in the output frame")
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "operator" => {
+                let operator = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing operator from {:?} to {:?}",
+                    settings.operator,
+                    operator
+                );
+                settings.operator = operator;
+            }
+            "magnitude" => {
+                let magnitude = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing magnitude from {:?} to {:?}",
+                    settings.magnitude,
+                    magnitude
+                );
+                settings.magnitude = magnitude;
+            }
+            "border-mode" => {
+                let border_mode = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing border mode from {:?} to {:?}",
+                    settings.border_mode,
+                    border_mode
+                );
+                settings.border_mode = border_mode;
+            }
+            "border-value" => {
+                let border_value = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing border value from {} to {}",
+                    settings.border_value,
+                    border_value
+                );
+                settings.border_value = border_value;
+            }
+            "n-threads" => {
+                let n_threads = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing number of threads from {} to {}",
+                    settings.n_threads,
+                    n_threads
+                );
+                settings.n_threads = n_threads;
+            }
+            "simd" => {
+                let simd = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing SIMD from {} to {}",
+                    settings.simd,
+                    simd
+                );
+                settings.simd = simd;
+            }
+            "output-mode" => {
+                let output_mode = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing output mode from {:?} to {:?}",
+                    settings.output_mode,
+                    output_mode
+                );
+                settings.output_mode = output_mode;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "operator" => settings.operator.to_value(),
+            "magnitude" => settings.magnitude.to_value(),
+            "border-mode" => settings.border_mode.to_value(),
+            "border-value" => settings.border_value.to_value(),
+            "n-threads" => settings.n_threads.to_value(),
+            "simd" => settings.simd.to_value(),
+            "output-mode" => settings.output_mode.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
 impl GstObjectImpl for CpuSobel {}
 impl ElementImpl for CpuSobel {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -52,7 +371,23 @@ impl ElementImpl for CpuSobel {
     fn pad_templates() -> &'static [gst::PadTemplate] {
         static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
             let caps = gst_video::VideoCapsBuilder::new()
-                .format(gst_video::VideoFormat::Rgbx)
+                .format_list([
+                    gst_video::VideoFormat::Rgbx,
+                    gst_video::VideoFormat::Bgrx,
+                    gst_video::VideoFormat::Xrgb,
+                    gst_video::VideoFormat::Xbgr,
+                    gst_video::VideoFormat::Rgba,
+                    gst_video::VideoFormat::Bgra,
+                    gst_video::VideoFormat::Argb,
+                    gst_video::VideoFormat::Abgr,
+                    gst_video::VideoFormat::Rgb,
+                    gst_video::VideoFormat::Bgr,
+                    gst_video::VideoFormat::Gray8,
+                    gst_video::VideoFormat::Gray16Le,
+                    gst_video::VideoFormat::I420,
+                    gst_video::VideoFormat::Nv12,
+                    gst_video::VideoFormat::Y444,
+                ])
                 .build();
             vec![
                 gst::PadTemplate::new(
@@ -79,6 +414,20 @@ impl BaseTransformImpl for CpuSobel {
     const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
     const PASSTHROUGH_ON_SAME_CAPS: bool = false;
     const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        match SimdKernel::detected() {
+            Some(simd) => gst::info!(CAT, imp = self, "CPU supports {} kernels", simd.name()),
+            None => gst::info!(CAT, imp = self, "No SIMD support, using scalar kernels"),
+        }
+        self.parent_start()
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        // Workers are not needed until the next start
+        *self.pool.lock().unwrap() = None;
+        self.parent_stop()
+    }
 }
 
 impl VideoFilterImpl for CpuSobel {
@@ -88,60 +437,45 @@ impl VideoFilterImpl for CpuSobel {
         outframe: &mut gst_video::VideoFrameRef<&mut gst::BufferRef>,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
         let start = Instant::now();
+        let settings = *self.settings.lock().unwrap();
 
-        // We will process line by line starting from the second
-        // let in_width = inframe.width() as usize;
+        let layout = PlaneLayout::new(inframe.info());
         let in_stride = inframe.plane_stride()[0] as usize;
-        let in_height = inframe.height() as usize;
         let out_stride = outframe.plane_stride()[0] as usize;
+        let format_info = inframe.format_info();
 
-        const MATRIX: [[i16; 3]; 3] = [
-            [1, 2, 1],    //
-            [0, 0, 0],    //
-            [-1, -2, -1], //
-        ];
-
-        let plane_data = inframe.plane_data(0).unwrap();
-        let out_data = outframe.plane_data_mut(0).unwrap();
-
-        for line in 1..(in_height - 1) {
-            let prev_line = line - 1;
-            let next_line = line + 1;
-            let line_offset = in_stride * line;
-            let prev_line_offset = in_stride * prev_line;
-            let next_line_offset = in_stride * next_line;
-
-            let channels = 4;
-            let window_size = channels * 3; // Sobel uses 3 x 3 window, but we working with color images, we fetch all colors at once
-
-            let line_data = &plane_data[line_offset..line_offset + in_stride];
-            let line_windows = line_data.windows(window_size).step_by(channels);
-            let prev_line_data = &plane_data[prev_line_offset..prev_line_offset + in_stride];
-            let prev_line_windows = prev_line_data.windows(window_size).step_by(channels);
-            let next_line_data = &plane_data[next_line_offset..next_line_offset + in_stride];
-            let next_line_windows = next_line_data.windows(window_size).step_by(channels);
-
-            let iter = prev_line_windows
-                .zip(line_windows)
-                .zip(next_line_windows)
-                .map(|x| {
-                    WindowDataRgbx {
-                        kernel: &MATRIX,
-                        prev_line_window: x.0 .0,
-                        line_window: x.0 .1,
-                        next_line_window: x.1,
-                    }
-                    .convolve()
-                });
+        let mut pool = self.pool.lock().unwrap();
+        let pool = WorkerPool::get_or_replace(&mut pool, settings.n_threads).map_err(|err| {
+            gst::element_imp_error!(
+                self,
+                gst::ResourceError::Failed,
+                ["Failed to spawn worker threads: {}", err]
+            );
+            gst::FlowError::Error
+        })?;
 
-            let out_line_data = &mut out_data[line_offset..line_offset + out_stride];
+        let in_plane = inframe.plane_data(0).unwrap();
+        let out_plane = outframe.plane_data_mut(0).unwrap();
+
+        match format_info.depth()[0] {
+            8 => Self::process_plane::<u8>(
+                pool, &settings, &layout, in_plane, in_stride, out_plane, out_stride,
+            ),
+            16 => Self::process_plane::<u16>(
+                pool, &settings, &layout, in_plane, in_stride, out_plane, out_stride,
+            ),
+            depth => {
+                gst::error!(CAT, imp = self, "Unsupported component depth {depth}");
+                return Err(gst::FlowError::NotSupported);
+            }
+        }
 
-            for (col_shifted, color) in iter.enumerate() {
-                let col = col_shifted + 1;
-                let write_pos = col * channels;
-                out_line_data[write_pos] = color.0;
-                out_line_data[write_pos + 1] = color.1;
-                out_line_data[write_pos + 2] = color.2;
+        // Chroma has no edges, fill it with neutral value so only luma edges are visible.
+        // All accepted YUV formats are 8 bit
+        if format_info.is_yuv() {
+            let neutral = 1u8 << (format_info.depth()[1] - 1);
+            for plane in 1..outframe.n_planes() {
+                outframe.plane_data_mut(plane).unwrap().fill(neutral);
             }
         }
 
@@ -157,47 +491,146 @@ impl VideoFilterImpl for CpuSobel {
     }
 }
 
-struct WindowDataRgbx<'a> {
-    kernel: &'a [[i16; 3]; 3],
-    prev_line_window: &'a [u8],
-    line_window: &'a [u8],
-    next_line_window: &'a [u8],
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl WindowDataRgbx<'_> {
-    #[inline(always)]
-    fn element_wise(&self, line: &[u8], kernel_line: &[i16]) -> (i16, i16, i16) {
-        const CHANNEL: usize = 4; // RGBx
+    const OPERATORS: [Operator; 6] = [
+        Operator::Sobel3,
+        Operator::Sobel5,
+        Operator::Sobel7,
+        Operator::Scharr,
+        Operator::Prewitt,
+        Operator::Roberts,
+    ];
+    const MAGNITUDES: [Magnitude; 3] = [Magnitude::L1, Magnitude::L2, Magnitude::Max];
+    const BORDER_MODES: [BorderMode; 6] = [
+        BorderMode::Constant,
+        BorderMode::Replicate,
+        BorderMode::Reflect,
+        BorderMode::Reflect101,
+        BorderMode::Wrap,
+        BorderMode::Zero,
+    ];
+    const OUTPUT_MODES: [OutputMode; 4] = [
+        OutputMode::Channels,
+        OutputMode::Gray,
+        OutputMode::Direction,
+        OutputMode::Components,
+    ];
 
-        let r_base = 0;
-        let g_base = 1;
-        let b_base = 2;
-        // x_base = 3; unused
+    /// GRAY8 and RGBA like planes of a frame, odd sizes leave a tail after the vector kernels
+    fn layouts() -> [PlaneLayout; 2] {
+        [
+            PlaneLayout {
+                width: 37,
+                height: 23,
+                pixel_stride: 1,
+                components: vec![0],
+                passthrough: vec![],
+            },
+            PlaneLayout {
+                width: 37,
+                height: 23,
+                pixel_stride: 4,
+                components: vec![0, 1, 2],
+                passthrough: vec![3],
+            },
+        ]
+    }
 
-        let r = line[r_base] as i16 * kernel_line[0]
-            + line[r_base + CHANNEL] as i16 * kernel_line[1]
-            + line[r_base + CHANNEL * 2] as i16 * kernel_line[2];
+    /// Processes a frame of pseudo-random samples, lines have a few padding samples at the end
+    fn process<T: Sample>(settings: &Settings, layout: &PlaneLayout, n_threads: usize) -> Vec<u8> {
+        let stride = (layout.width * layout.pixel_stride + 3) * T::BYTES;
+        let mut state = 1u32;
+        let in_plane: Vec<u8> = (0..stride * layout.height)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect();
 
-        let g = line[g_base] as i16 * kernel_line[0]
-            + line[g_base + CHANNEL] as i16 * kernel_line[1]
-            + line[g_base + CHANNEL * 2] as i16 * kernel_line[2];
+        let pool = WorkerPool::new(n_threads).unwrap();
+        let mut out_plane = vec![0; in_plane.len()];
+        CpuSobel::process_plane::<T>(
+            &pool,
+            settings,
+            layout,
+            &in_plane,
+            stride,
+            &mut out_plane,
+            stride,
+        );
+        out_plane
+    }
+
+    #[test]
+    fn stripes_match_single_thread() {
+        for layout in layouts() {
+            for (operator, magnitude, output_mode) in OPERATORS.into_iter().flat_map(|op| {
+                MAGNITUDES.into_iter().flat_map(move |magnitude| {
+                    OUTPUT_MODES.map(|output_mode| (op, magnitude, output_mode))
+                })
+            }) {
+                for border_mode in [BorderMode::Reflect101, BorderMode::Constant] {
+                    let settings = Settings {
+                        operator,
+                        magnitude,
+                        border_mode,
+                        border_value: 100,
+                        output_mode,
+                        ..Settings::default()
+                    };
 
-        let b = line[b_base] as i16 * kernel_line[0]
-            + line[b_base + CHANNEL] as i16 * kernel_line[1]
-            + line[b_base + CHANNEL * 2] as i16 * kernel_line[2];
+                    let expected = process::<u8>(&settings, &layout, 1);
+                    for n_threads in [2, 5, 32] {
+                        assert!(
+                            process::<u8>(&settings, &layout, n_threads) == expected,
+                            "{settings:?} with {n_threads} threads"
+                        );
+                    }
 
-        (r, g, b)
+                    let expected = process::<u16>(&settings, &layout, 1);
+                    assert!(
+                        process::<u16>(&settings, &layout, 5) == expected,
+                        "{settings:?} of 16 bit samples"
+                    );
+                }
+            }
+        }
     }
 
-    fn convolve(&self) -> (u8, u8, u8) {
-        let (prev_r, prev_g, prev_b) = self.element_wise(&self.prev_line_window, &self.kernel[0]);
-        let (curr_r, curr_g, curr_b) = self.element_wise(&self.line_window, &self.kernel[1]);
-        let (next_r, next_g, next_b) = self.element_wise(&self.next_line_window, &self.kernel[2]);
+    #[test]
+    fn vector_kernels_match_scalar() {
+        if let Some(simd) = SimdKernel::detected() {
+            eprintln!("Comparing {} kernels with scalar code", simd.name());
+        }
+
+        for layout in layouts() {
+            for (operator, magnitude, border_mode) in OPERATORS.into_iter().flat_map(|op| {
+                MAGNITUDES.into_iter().flat_map(move |magnitude| {
+                    BORDER_MODES.map(|border_mode| (op, magnitude, border_mode))
+                })
+            }) {
+                let settings = Settings {
+                    operator,
+                    magnitude,
+                    border_mode,
+                    border_value: 100,
+                    simd: true,
+                    output_mode: OutputMode::Channels,
+                    ..Settings::default()
+                };
+                let scalar = Settings {
+                    simd: false,
+                    ..settings
+                };
 
-        (
-            (prev_r + curr_r + next_r).clamp(0, 255) as u8,
-            (prev_g + curr_g + next_g).clamp(0, 255) as u8,
-            (prev_b + curr_b + next_b).clamp(0, 255) as u8,
-        )
+                assert!(
+                    process::<u8>(&settings, &layout, 1) == process::<u8>(&scalar, &layout, 1),
+                    "{settings:?}"
+                );
+            }
+        }
     }
 }
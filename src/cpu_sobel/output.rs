@@ -0,0 +1,136 @@
+//!
+//! Visualizations of the gradient written into the output frame
+//!
+
+use crate::glib;
+
+use super::imp::Magnitude;
+use crate::cpu_common::sample::Sample;
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstDekaSobelOutputMode")]
+pub enum OutputMode {
+    #[enum_value(
+        name = "Channels: gradient magnitude of each color channel",
+        nick = "channels"
+    )]
+    Channels = 0,
+    #[enum_value(
+        name = "Gray: gradient magnitude of luma in all channels",
+        nick = "gray"
+    )]
+    Gray = 1,
+    #[enum_value(
+        name = "Direction: gradient direction as hue and magnitude as value",
+        nick = "direction"
+    )]
+    Direction = 2,
+    #[enum_value(
+        name = "Components: Gx in red and Gy in green with half range offset",
+        nick = "components"
+    )]
+    Components = 3,
+}
+
+impl OutputMode {
+    /// Writes the pixel with `gradients` of the color `components` into `out`
+    ///
+    /// `norm` is the sum of positive kernel coefficients, the largest response is `norm * T::MAX`.
+    /// Formats with a single color component get direction as the angle and only Gx for components
+    pub fn write_pixel<T: Sample>(
+        self,
+        magnitude: Magnitude,
+        norm: i32,
+        gradients: &[(i32, i32)],
+        components: &[usize],
+        out: &mut [u8],
+    ) {
+        let mut store = |component: usize, value: u32| {
+            T::from_u32_clamped(value).write(&mut out[component * T::BYTES..])
+        };
+
+        match self {
+            OutputMode::Channels => {
+                for (&(gx, gy), &component) in gradients.iter().zip(components) {
+                    store(component, magnitude.combine(gx, gy));
+                }
+            }
+            OutputMode::Gray => {
+                let (gx, gy) = luma(gradients);
+                let value = magnitude.combine(gx, gy);
+                for &component in components {
+                    store(component, value);
+                }
+            }
+            OutputMode::Direction => {
+                let (gx, gy) = luma(gradients);
+                let max = T::MAX as f32;
+                let value = magnitude.combine(gx, gy).min(T::MAX) as f32 / max;
+                let hue = (gy as f32).atan2(gx as f32).to_degrees().rem_euclid(360.0);
+
+                match *components {
+                    [r, g, b] => {
+                        let rgb = hsv_to_rgb(hue, value);
+                        store(r, (rgb[0] * max).round() as u32);
+                        store(g, (rgb[1] * max).round() as u32);
+                        store(b, (rgb[2] * max).round() as u32);
+                    }
+                    _ => {
+                        let angle = if value > 0.0 { hue / 360.0 * max } else { 0.0 };
+                        for &component in components {
+                            store(component, angle as u32);
+                        }
+                    }
+                }
+            }
+            OutputMode::Components => {
+                let (gx, gy) = luma(gradients);
+                let offset = T::MAX.div_ceil(2) as i32;
+                let scale = |gradient: i32| (offset + gradient / (2 * norm)).max(0) as u32;
+
+                match *components {
+                    [r, g, b] => {
+                        store(r, scale(gx));
+                        store(g, scale(gy));
+                        store(b, 0);
+                    }
+                    _ => {
+                        for &component in components {
+                            store(component, scale(gx));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Gradient of luma computed from the gradients of RGB channels with BT.601 weights
+#[inline(always)]
+fn luma(gradients: &[(i32, i32)]) -> (i32, i32) {
+    // Weighted sum of the large kernel responses does not fit into i32
+    let weigh =
+        |r: i32, g: i32, b: i32| ((77 * r as i64 + 150 * g as i64 + 29 * b as i64) >> 8) as i32;
+
+    match *gradients {
+        [(rx, ry), (gx, gy), (bx, by)] => (weigh(rx, gx, bx), weigh(ry, gy, by)),
+        [gradient, ..] => gradient,
+        [] => (0, 0),
+    }
+}
+
+/// Fully saturated color of `hue` in degrees and `value` within `0.0..=1.0`
+fn hsv_to_rgb(hue: f32, value: f32) -> [f32; 3] {
+    let sector = hue / 60.0;
+    let x = value * (1.0 - (sector % 2.0 - 1.0).abs());
+
+    match sector as u32 {
+        0 => [value, x, 0.0],
+        1 => [x, value, 0.0],
+        2 => [0.0, value, x],
+        3 => [0.0, x, value],
+        4 => [x, 0.0, value],
+        _ => [value, 0.0, x],
+    }
+}
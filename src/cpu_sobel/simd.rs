@@ -0,0 +1,280 @@
+//!
+//! Vectorized 3x3 Sobel, Scharr and Prewitt for 8 bit samples with runtime CPU feature dispatch
+//!
+
+use std::sync::LazyLock;
+
+use super::imp::Magnitude;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    #[cfg(target_arch = "x86_64")]
+    Sse2,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+/// Vector kernels for the instruction set supported by the running CPU
+///
+/// Can only be obtained with [`SimdKernel::detected`], so the kernels are always safe to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimdKernel {
+    level: Level,
+}
+
+impl SimdKernel {
+    /// Best kernels for the CPU or `None` if only scalar code can be used
+    pub fn detected() -> Option<Self> {
+        static DETECTED: LazyLock<Option<SimdKernel>> = LazyLock::new(|| {
+            #[cfg(target_arch = "x86_64")]
+            {
+                if is_x86_feature_detected!("avx2") {
+                    return Some(SimdKernel { level: Level::Avx2 });
+                }
+                if is_x86_feature_detected!("sse2") {
+                    return Some(SimdKernel { level: Level::Sse2 });
+                }
+            }
+
+            None
+        });
+
+        *DETECTED
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.level {
+            #[cfg(target_arch = "x86_64")]
+            Level::Sse2 => "SSE2",
+            #[cfg(target_arch = "x86_64")]
+            Level::Avx2 => "AVX2",
+        }
+    }
+
+    /// Computes gradient magnitude of every sample of the row, the result is the same as of
+    /// the scalar code
+    ///
+    /// `rows` are previous, current and next rows extended by one pixel of `ps` samples on each side,
+    /// `weights` are `[a, b]` of the `[a, b, a]` smoothing, `out` is the unpadded output row
+    ///
+    /// # Panics
+    /// Panics if the weights are larger than Scharr ones, gradients would not fit into 16 bit lanes
+    pub fn sobel_row(
+        &self,
+        rows: [&[u8]; 3],
+        ps: usize,
+        weights: [i32; 2],
+        magnitude: Magnitude,
+        out: &mut [u8],
+    ) {
+        assert!(rows.iter().all(|row| row.len() >= out.len() + 2 * ps));
+        assert!(weights.iter().all(|w| (0..=10).contains(w)) && 2 * weights[0] + weights[1] <= 16);
+
+        // SAFETY: the level is only selected when CPU supports it and row bounds are checked above
+        let done = match self.level {
+            #[cfg(target_arch = "x86_64")]
+            Level::Sse2 => unsafe { x86::sobel_row_sse2(rows, ps, weights, magnitude, out) },
+            #[cfg(target_arch = "x86_64")]
+            Level::Avx2 => unsafe { x86::sobel_row_avx2(rows, ps, weights, magnitude, out) },
+        };
+
+        // Tail which does not fill the whole vector
+        for (i, sample) in out.iter_mut().enumerate().skip(done) {
+            *sample = sobel_sample(rows, i + ps, ps, weights, magnitude);
+        }
+    }
+}
+
+#[inline(always)]
+fn sobel_sample(
+    rows: [&[u8]; 3],
+    center: usize,
+    ps: usize,
+    [a, b]: [i32; 2],
+    magnitude: Magnitude,
+) -> u8 {
+    let [top, mid, bottom] = rows.map(|row| {
+        [
+            row[center - ps] as i32,
+            row[center] as i32,
+            row[center + ps] as i32,
+        ]
+    });
+
+    let gx = a * (top[2] - top[0]) + b * (mid[2] - mid[0]) + a * (bottom[2] - bottom[0]);
+    let gy = a * (top[0] - bottom[0]) + b * (top[1] - bottom[1]) + a * (top[2] - bottom[2]);
+
+    magnitude.combine(gx, gy).min(u8::MAX as u32) as u8
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    //! All kernels return the number of processed samples, which is a multiple of vector width.
+    //! Gradients of 8 bit samples are within ±4080 for Scharr weights, so they are computed
+    //! in 16 bit lanes.
+    //! L2 is computed in f32 with truncation exactly like the scalar code does
+
+    use std::arch::x86_64::*;
+
+    use super::Magnitude;
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn load_sse2(row: &[u8], at: usize) -> __m128i {
+        let bytes = _mm_loadl_epi64(row.as_ptr().add(at).cast());
+        _mm_unpacklo_epi8(bytes, _mm_setzero_si128())
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn abs_sse2(x: __m128i) -> __m128i {
+        _mm_max_epi16(x, _mm_sub_epi16(_mm_setzero_si128(), x))
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn l2_sse2(pairs: __m128i) -> __m128i {
+        // Interleaved (gx, gy) pairs are turned into gx^2 + gy^2 by a single multiply-add
+        let squares = _mm_cvtepi32_ps(_mm_madd_epi16(pairs, pairs));
+        _mm_cvttps_epi32(_mm_sqrt_ps(squares))
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn sobel_row_sse2(
+        rows: [&[u8]; 3],
+        ps: usize,
+        [a, b]: [i32; 2],
+        magnitude: Magnitude,
+        out: &mut [u8],
+    ) -> usize {
+        const LANES: usize = 8;
+
+        let [top, mid, bottom] = rows;
+        let (a, b) = (_mm_set1_epi16(a as i16), _mm_set1_epi16(b as i16));
+        let mut i = 0;
+        while i + LANES <= out.len() {
+            let center = i + ps;
+            let (tl, tc, tr) = (
+                load_sse2(top, center - ps),
+                load_sse2(top, center),
+                load_sse2(top, center + ps),
+            );
+            let (ml, mr) = (load_sse2(mid, center - ps), load_sse2(mid, center + ps));
+            let (bl, bc, br) = (
+                load_sse2(bottom, center - ps),
+                load_sse2(bottom, center),
+                load_sse2(bottom, center + ps),
+            );
+
+            // gx = a * ((tr - tl) + (br - bl)) + b * (mr - ml)
+            let gx = _mm_add_epi16(
+                _mm_mullo_epi16(
+                    a,
+                    _mm_add_epi16(_mm_sub_epi16(tr, tl), _mm_sub_epi16(br, bl)),
+                ),
+                _mm_mullo_epi16(b, _mm_sub_epi16(mr, ml)),
+            );
+            // gy = a * ((tl + tr) - (bl + br)) + b * (tc - bc)
+            let gy = _mm_add_epi16(
+                _mm_mullo_epi16(
+                    a,
+                    _mm_sub_epi16(_mm_add_epi16(tl, tr), _mm_add_epi16(bl, br)),
+                ),
+                _mm_mullo_epi16(b, _mm_sub_epi16(tc, bc)),
+            );
+
+            let result = match magnitude {
+                Magnitude::L1 => _mm_add_epi16(abs_sse2(gx), abs_sse2(gy)),
+                Magnitude::Max => _mm_max_epi16(abs_sse2(gx), abs_sse2(gy)),
+                Magnitude::L2 => _mm_packs_epi32(
+                    l2_sse2(_mm_unpacklo_epi16(gx, gy)),
+                    l2_sse2(_mm_unpackhi_epi16(gx, gy)),
+                ),
+            };
+
+            _mm_storel_epi64(
+                out.as_mut_ptr().add(i).cast(),
+                _mm_packus_epi16(result, result),
+            );
+            i += LANES;
+        }
+
+        i
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_avx2(row: &[u8], at: usize) -> __m256i {
+        _mm256_cvtepu8_epi16(_mm_loadu_si128(row.as_ptr().add(at).cast()))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn l2_avx2(pairs: __m256i) -> __m256i {
+        let squares = _mm256_cvtepi32_ps(_mm256_madd_epi16(pairs, pairs));
+        _mm256_cvttps_epi32(_mm256_sqrt_ps(squares))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sobel_row_avx2(
+        rows: [&[u8]; 3],
+        ps: usize,
+        [a, b]: [i32; 2],
+        magnitude: Magnitude,
+        out: &mut [u8],
+    ) -> usize {
+        const LANES: usize = 16;
+
+        let [top, mid, bottom] = rows;
+        let (a, b) = (_mm256_set1_epi16(a as i16), _mm256_set1_epi16(b as i16));
+        let mut i = 0;
+        while i + LANES <= out.len() {
+            let center = i + ps;
+            let (tl, tc, tr) = (
+                load_avx2(top, center - ps),
+                load_avx2(top, center),
+                load_avx2(top, center + ps),
+            );
+            let (ml, mr) = (load_avx2(mid, center - ps), load_avx2(mid, center + ps));
+            let (bl, bc, br) = (
+                load_avx2(bottom, center - ps),
+                load_avx2(bottom, center),
+                load_avx2(bottom, center + ps),
+            );
+
+            let gx = _mm256_add_epi16(
+                _mm256_mullo_epi16(
+                    a,
+                    _mm256_add_epi16(_mm256_sub_epi16(tr, tl), _mm256_sub_epi16(br, bl)),
+                ),
+                _mm256_mullo_epi16(b, _mm256_sub_epi16(mr, ml)),
+            );
+            let gy = _mm256_add_epi16(
+                _mm256_mullo_epi16(
+                    a,
+                    _mm256_sub_epi16(_mm256_add_epi16(tl, tr), _mm256_add_epi16(bl, br)),
+                ),
+                _mm256_mullo_epi16(b, _mm256_sub_epi16(tc, bc)),
+            );
+
+            let result = match magnitude {
+                Magnitude::L1 => _mm256_add_epi16(_mm256_abs_epi16(gx), _mm256_abs_epi16(gy)),
+                Magnitude::Max => _mm256_max_epi16(_mm256_abs_epi16(gx), _mm256_abs_epi16(gy)),
+                // Unpacking and packing both work within 128 bit lanes, so the order is preserved
+                Magnitude::L2 => _mm256_packs_epi32(
+                    l2_avx2(_mm256_unpacklo_epi16(gx, gy)),
+                    l2_avx2(_mm256_unpackhi_epi16(gx, gy)),
+                ),
+            };
+
+            let packed = _mm_packus_epi16(
+                _mm256_castsi256_si128(result),
+                _mm256_extracti128_si256::<1>(result),
+            );
+            _mm_storeu_si128(out.as_mut_ptr().add(i).cast(), packed);
+            i += LANES;
+        }
+
+        i
+    }
+}
@@ -1,10 +1,15 @@
+mod debug_hook;
 mod glow_context;
 pub mod transition;
 
 pub use glow_context::{
-    GlowContext, GstElementFindGlowContextExt, GstElementGetGlowContextExt, GST_CONTEXT_GLOW_TYPE,
+    GlowContext, GstElementFindGlowContextExt, GstElementGetGlowContextExt,
+    GstElementHandleGlowContextQueryExt, GST_CONTEXT_GLOW_TYPE,
 };
 
 pub mod prelude {
-    pub use super::transition::{AsGlowProgram, AsGlowTexture};
+    pub use super::transition::{
+        AsGlowBuffer, AsGlowFence, AsGlowFramebuffer, AsGlowProgram, AsGlowShader, AsGlowTexture,
+        IntoGLMemory,
+    };
 }
@@ -0,0 +1,264 @@
+//!
+//! KHR_debug callback shared by the glow contexts of a GL context
+//!
+//! A GL context has a single debug callback. libgstgl installs its own when its `gldebug`
+//! category is enabled, so the hook chains to whatever was installed before and puts it back once
+//! the last glow context of the GL context is gone. Installing and removing happen under one lock
+//! on the GL thread, so a glow context finalized while another one is created for the same GL
+//! context cannot remove the callback of the new one.
+//!
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_void, CStr},
+    sync::{LazyLock, Mutex},
+};
+
+use glow::HasContext;
+use gst_gl::prelude::*;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "gstglowdebug",
+        gst::DebugColorFlags::empty(),
+        Some("KHR_debug messages of glow contexts"),
+    )
+});
+
+/// `GLDEBUGPROC`
+type DebugProc = unsafe extern "system" fn(u32, u32, u32, u32, i32, *const c_char, *const c_void);
+type GetPointervProc = unsafe extern "system" fn(u32, *mut *mut c_void);
+type DebugMessageCallbackProc = unsafe extern "system" fn(Option<DebugProc>, *const c_void);
+
+/// Hooks of the GL contexts with glow contexts, boxed so they keep their address when the vector
+/// grows
+#[allow(clippy::vec_box)]
+static HOOKS: LazyLock<Mutex<Vec<Box<Hook>>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+thread_local! {
+    /// GL errors reported while [`capture`] runs on this thread
+    static CAPTURED_ERRORS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Installed callback of a GL context, its address is the user parameter of the callback
+struct Hook {
+    context: gst_gl::GLContext,
+    /// Number of glow contexts using the hook
+    users: usize,
+    /// Callback installed before the hook with its user parameter
+    previous: Option<(DebugProc, usize)>,
+    /// Whether `DEBUG_OUTPUT` was enabled before the hook
+    debug_output: bool,
+    set_callback: DebugMessageCallbackProc,
+}
+
+/// Suffix of the debug functions, the core ones or those of the KHR_debug extension on GLES
+/// before 3.2, `None` without KHR_debug
+fn function_suffix(gl: &glow::Context) -> Option<&'static str> {
+    let version = gl.version();
+    let core_debug = if version.is_embedded {
+        (version.major, version.minor) >= (3, 2)
+    } else {
+        (version.major, version.minor) >= (4, 3)
+    };
+    if core_debug {
+        Some("")
+    } else if gl.supported_extensions().contains("GL_KHR_debug") {
+        Some(if version.is_embedded { "KHR" } else { "" })
+    } else {
+        None
+    }
+}
+
+/// Installs the hook on `context` or counts one more user of it, must be called on its GL thread
+///
+/// Forwards the driver messages into the debug log, GL errors also end up in [`capture`].
+pub(super) unsafe fn acquire(gl: &glow::Context, context: &gst_gl::GLContext) {
+    let mut hooks = HOOKS.lock().unwrap();
+    if let Some(hook) = hooks.iter_mut().find(|hook| hook.context == *context) {
+        hook.users += 1;
+        return;
+    }
+
+    let Some(suffix) = function_suffix(gl) else {
+        gst::info!(
+            CAT,
+            obj = context,
+            "KHR_debug is not available, GL messages are not logged"
+        );
+        return;
+    };
+    let get_pointer = context.proc_address(&format!("glGetPointerv{suffix}"));
+    let set_callback = context.proc_address(&format!("glDebugMessageCallback{suffix}"));
+    if get_pointer == 0 || set_callback == 0 {
+        gst::warning!(CAT, obj = context, "KHR_debug functions are not loadable");
+        return;
+    }
+    let get_pointer = std::mem::transmute::<usize, GetPointervProc>(get_pointer);
+    let set_callback = std::mem::transmute::<usize, DebugMessageCallbackProc>(set_callback);
+
+    let mut callback = std::ptr::null_mut();
+    let mut user_param = std::ptr::null_mut();
+    get_pointer(glow::DEBUG_CALLBACK_FUNCTION, &mut callback);
+    get_pointer(glow::DEBUG_CALLBACK_USER_PARAM, &mut user_param);
+    let previous = (!callback.is_null()).then(|| {
+        gst::debug!(
+            CAT,
+            obj = context,
+            "Chaining to the installed GL debug callback"
+        );
+        (
+            std::mem::transmute::<*mut c_void, DebugProc>(callback),
+            user_param as usize,
+        )
+    });
+
+    let hook = Box::new(Hook {
+        context: context.clone(),
+        users: 1,
+        previous,
+        debug_output: gl.is_enabled(glow::DEBUG_OUTPUT),
+        set_callback,
+    });
+    set_callback(Some(debug_message), &*hook as *const Hook as *const c_void);
+    gl.enable(glow::DEBUG_OUTPUT);
+    hooks.push(hook);
+
+    gst::debug!(CAT, obj = context, "Installed GL debug callback");
+}
+
+/// Counts one user less of the hook on `context` and puts the previous callback back after the
+/// last one, must be called on its GL thread
+pub(super) unsafe fn release(gl: &glow::Context, context: &gst_gl::GLContext) {
+    let mut hooks = HOOKS.lock().unwrap();
+    let Some(idx) = hooks.iter().position(|hook| hook.context == *context) else {
+        return;
+    };
+    hooks[idx].users -= 1;
+    if hooks[idx].users > 0 {
+        return;
+    }
+
+    let hook = hooks.swap_remove(idx);
+    match hook.previous {
+        Some((callback, user_param)) => {
+            (hook.set_callback)(Some(callback), user_param as *const c_void)
+        }
+        None => (hook.set_callback)(None, std::ptr::null()),
+    }
+    if !hook.debug_output {
+        gl.disable(glow::DEBUG_OUTPUT);
+    }
+    drop(hooks);
+
+    gst::debug!(CAT, obj = context, "Removed GL debug callback");
+}
+
+/// Runs `func` and returns the GL errors the driver reported meanwhile, must be called on the GL
+/// thread of `gl`
+///
+/// Messages are synchronous while capturing so they arrive inside the GL call causing them, on
+/// the calling thread. The rest of the time the driver is free to report them later.
+pub(super) fn capture<R>(gl: &glow::Context, func: impl FnOnce() -> R) -> (R, Vec<String>) {
+    let outer = CAPTURED_ERRORS.with_borrow_mut(|errors| errors.replace(Vec::new()));
+    let synchronous = outer.is_none() && !unsafe { gl.is_enabled(glow::DEBUG_OUTPUT_SYNCHRONOUS) };
+    if synchronous {
+        unsafe { gl.enable(glow::DEBUG_OUTPUT_SYNCHRONOUS) };
+    }
+
+    let result = func();
+
+    if synchronous {
+        unsafe { gl.disable(glow::DEBUG_OUTPUT_SYNCHRONOUS) };
+    }
+    let captured = CAPTURED_ERRORS.with_borrow_mut(|errors| {
+        let captured = errors.take().unwrap_or_default();
+        // An enclosing capture sees the errors as well
+        *errors = outer.map(|mut outer| {
+            outer.extend(captured.iter().cloned());
+            outer
+        });
+        captured
+    });
+
+    (result, captured)
+}
+
+unsafe extern "system" fn debug_message(
+    source: u32,
+    kind: u32,
+    id: u32,
+    severity: u32,
+    length: i32,
+    message: *const c_char,
+    user_param: *const c_void,
+) {
+    // SAFETY: the hook stays boxed in `HOOKS` while it is installed
+    let hook = &*(user_param as *const Hook);
+    if let Some((callback, user_param)) = hook.previous {
+        callback(
+            source,
+            kind,
+            id,
+            severity,
+            length,
+            message,
+            user_param as *const c_void,
+        );
+    }
+
+    let text = match usize::try_from(length) {
+        Ok(length) => {
+            String::from_utf8_lossy(std::slice::from_raw_parts(message as *const u8, length))
+        }
+        // Negative lengths mean a null terminated message
+        Err(_) => CStr::from_ptr(message).to_string_lossy(),
+    };
+    let level = match severity {
+        glow::DEBUG_SEVERITY_HIGH => gst::DebugLevel::Error,
+        glow::DEBUG_SEVERITY_MEDIUM => gst::DebugLevel::Warning,
+        glow::DEBUG_SEVERITY_LOW => gst::DebugLevel::Info,
+        _ => gst::DebugLevel::Log,
+    };
+    gst::log_with_level!(
+        CAT,
+        level,
+        obj = hook.context,
+        "GL {} {} message {}: {}",
+        source_name(source),
+        type_name(kind),
+        id,
+        text
+    );
+
+    if kind == glow::DEBUG_TYPE_ERROR {
+        CAPTURED_ERRORS.with_borrow_mut(|errors| {
+            if let Some(errors) = errors {
+                errors.push(text.into_owned());
+            }
+        });
+    }
+}
+
+fn source_name(source: u32) -> &'static str {
+    match source {
+        glow::DEBUG_SOURCE_API => "API",
+        glow::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
+        glow::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
+        glow::DEBUG_SOURCE_THIRD_PARTY => "third party",
+        glow::DEBUG_SOURCE_APPLICATION => "application",
+        _ => "other",
+    }
+}
+
+fn type_name(kind: u32) -> &'static str {
+    match kind {
+        glow::DEBUG_TYPE_ERROR => "error",
+        glow::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behavior",
+        glow::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        glow::DEBUG_TYPE_PORTABILITY => "portability",
+        glow::DEBUG_TYPE_PERFORMANCE => "performance",
+        glow::DEBUG_TYPE_MARKER => "marker",
+        _ => "other",
+    }
+}
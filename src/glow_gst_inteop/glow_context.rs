@@ -1,11 +1,17 @@
-use std::{ops::ControlFlow, sync::LazyLock};
+use std::{
+    ops::ControlFlow,
+    sync::{LazyLock, Mutex},
+};
 
 use glib::subclass::types::ObjectSubclassIsExt;
-use gst::{glib::subclass::types::ObjectSubclassExt, prelude::*, subclass::prelude::*};
-use gst_gl::{prelude::*, GLBaseFilter};
+use glib::translate::from_glib_none;
+use gst::prelude::*;
+use gst_gl::{prelude::*, GLBaseFilter, GLBaseSrc};
 
 use crate::glib;
 
+use super::debug_hook;
+
 /// GstContext type string
 pub const GST_CONTEXT_GLOW_TYPE: &str = "rust.glow.Context";
 
@@ -19,6 +25,10 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     )
 });
 
+/// Glow wrappers of the GL contexts, so elements on the same GL context share one
+static GLOW_CONTEXTS: LazyLock<Mutex<Vec<glib::WeakRef<GlowContext>>>> =
+    LazyLock::new(|| Mutex::new(Vec::new()));
+
 glib::wrapper! {
 
     pub struct GlowContext(ObjectSubclass<imp::GlowContext>);
@@ -27,15 +37,16 @@ glib::wrapper! {
 impl GlowContext {
     /// Creates a new glow context for the given `parent` GL context from gstreamer-gl
     ///
-    /// # Note
-    /// The glow does not specify safety for `Context::from_loader_function` so this function is unsafe without
-    /// any safety requirements
+    /// # Safety
+    /// Must be called on the GL thread of `parent`, which gets the debug callback of the glow contexts.
+    /// The glow does not specify safety for `Context::from_loader_function` beyond that
     pub unsafe fn new(parent: &gst_gl::GLContext) -> Self {
         let wrapper = glow::Context::from_loader_function({
             let ctx = parent.clone();
 
             move |name| ctx.proc_address(name) as *const std::ffi::c_void
         });
+        debug_hook::acquire(&wrapper, parent);
 
         let out: Self = glib::Object::new();
         let imp = out.imp();
@@ -63,14 +74,122 @@ impl GlowContext {
         ctx
     }
 
+    /// Glow context for issuing GL calls, only valid on the GL thread with the context current
+    ///
+    /// Prefer [`GlowContext::with_glow`] when the calling thread is not known. Debug builds
+    /// assert that the context is current.
     #[inline]
     pub fn glow(&self) -> &glow::Context {
+        debug_assert!(
+            self.is_current(),
+            "glow context is accessed off the GL thread, use GlowContext::with_glow"
+        );
+        self.glow_unchecked()
+    }
+
+    #[inline]
+    fn glow_unchecked(&self) -> &glow::Context {
         let out = unsafe { &*self.imp().inner.get() };
         out.as_ref()
             .map(|x| &x.context)
             .expect("inner is None, you must create GlowContext using associated GlowContext::new")
     }
 
+    /// Whether the parent GL context or a compatible one is current in the calling thread
+    pub fn is_current(&self) -> bool {
+        gst_gl::GLContext::current().is_some_and(|current| self.is_compatible(&current))
+    }
+
+    /// Runs `func` and returns the GL errors the driver reported meanwhile, must be called on the
+    /// GL thread
+    ///
+    /// Contexts without KHR_debug never report errors, neither do the contexts sharing with the
+    /// parent, which have no debug callback of this context.
+    pub fn capture_errors<R>(&self, func: impl FnOnce() -> R) -> (R, Vec<String>) {
+        debug_assert!(
+            self.is_current(),
+            "GL errors are captured off the GL thread"
+        );
+
+        debug_hook::capture(self.glow_unchecked(), func)
+    }
+
+    /// Whether GL calls through this context are valid for `gl_context`
+    ///
+    /// That is the parent context and the contexts sharing with it on the same display and GL API,
+    /// which see the same objects through the same function pointers. The debug callback only
+    /// reports the messages of the parent though.
+    pub fn is_compatible(&self, gl_context: &gst_gl::GLContext) -> bool {
+        let parent = self.gst_gl_context();
+        parent == gl_context
+            || (parent.can_share(gl_context)
+                && parent.display() == gl_context.display()
+                && parent.gl_api() == gl_context.gl_api())
+    }
+
+    /// Upgrades the cached glow contexts and forgets the dropped ones
+    ///
+    /// The strong references must outlive the lock: dropping the last one of another GL context
+    /// dispatches to its GL thread, which may be waiting for the lock itself.
+    fn alive_contexts(contexts: &mut Vec<glib::WeakRef<GlowContext>>) -> Vec<GlowContext> {
+        let alive = contexts
+            .iter()
+            .filter_map(|weak| weak.upgrade())
+            .collect::<Vec<_>>();
+        *contexts = alive.iter().map(|glow| glow.downgrade()).collect();
+        alive
+    }
+
+    /// Returns the glow context already wrapping `parent` or creates one
+    ///
+    /// Both happen on the GL thread of `parent`, which runs them one after the other, so there is
+    /// never a second wrapper of the same GL context to be thrown away.
+    fn for_gl_context(parent: &gst_gl::GLContext) -> Option<GlowContext> {
+        let mut glow = None;
+        parent.thread_add(|ctx| {
+            let mut contexts = GLOW_CONTEXTS.lock().unwrap();
+            let alive = Self::alive_contexts(&mut contexts);
+            let found = alive
+                .iter()
+                .find(|glow| glow.gst_gl_context() == ctx)
+                .cloned()
+                .unwrap_or_else(|| {
+                    // Should be safe to call this in GL thread
+                    let created = unsafe { GlowContext::new(ctx) };
+                    contexts.push(created.downgrade());
+                    created
+                });
+            drop(contexts);
+            drop(alive);
+
+            glow = Some(found);
+        });
+
+        glow
+    }
+
+    /// Runs `func` with the glow context on the GL thread and returns its result
+    ///
+    /// `func` runs directly when the context is already current, otherwise it is dispatched with
+    /// `GLContext::thread_add`, which blocks until it returns.
+    pub fn with_glow<R, F>(&self, func: F) -> R
+    where
+        R: Send,
+        F: FnOnce(&glow::Context) -> R + Send,
+    {
+        if self.is_current() {
+            return func(self.glow_unchecked());
+        }
+
+        gst::trace!(CAT, obj = self, "Dispatching to the GL thread");
+        let mut result = None;
+        self.gst_gl_context().thread_add(|_| {
+            result = Some(func(self.glow_unchecked()));
+        });
+
+        result.expect("thread_add returns after running the function")
+    }
+
     #[inline]
     pub fn gst_gl_context(&self) -> &gst_gl::GLContext {
         let out = unsafe { &*self.imp().inner.get() };
@@ -79,7 +198,11 @@ impl GlowContext {
             .expect("inner is None, you must create GlowContext using associated GlowContext::new")
     }
 
-    fn query_context_pad(element: &gst::Element, pad: &gst::Pad) -> bool {
+    fn query_context_pad(
+        element: &gst::Element,
+        pad: &gst::Pad,
+        gl_context: &gst_gl::GLContext,
+    ) -> bool {
         let mut query = gst::query::Context::new(GST_CONTEXT_GLOW_TYPE);
         let remote_pad = pad.peer();
         let remote_element_name = remote_pad
@@ -104,6 +227,19 @@ impl GlowContext {
             return false;
         };
 
+        let compatible = Self::map_gst_context_to_glow(pad_ctx.clone())
+            .is_some_and(|glow| glow.is_compatible(gl_context));
+        if !compatible {
+            gst::info!(
+                CAT,
+                obj = element,
+                "ignoring context of other GL context from pad {} from element {:?}",
+                pad.name(),
+                remote_element_name
+            );
+            return false;
+        }
+
         gst::info!(
             CAT,
             obj = element,
@@ -119,9 +255,10 @@ impl GlowContext {
 
     fn query_context_pad_fn<'a>(
         found: &'a mut bool,
+        gl_context: &'a gst_gl::GLContext,
     ) -> impl FnMut(&gst::Element, &gst::Pad) -> ControlFlow<()> + 'a {
         move |element, pad| {
-            if Self::query_context_pad(element, pad) {
+            if Self::query_context_pad(element, pad, gl_context) {
                 *found = true;
                 ControlFlow::Break(())
             } else {
@@ -130,26 +267,31 @@ impl GlowContext {
         }
     }
 
-    fn query_context_from_pads(element: &gst::Element) -> bool {
+    fn query_context_from_pads(element: &gst::Element, gl_context: &gst_gl::GLContext) -> bool {
         let mut found = false;
 
-        element.foreach_src_pad(Self::query_context_pad_fn(&mut found));
+        element.foreach_src_pad(Self::query_context_pad_fn(&mut found, gl_context));
         if found {
             return found;
         }
 
-        element.foreach_sink_pad(Self::query_context_pad_fn(&mut found));
+        element.foreach_sink_pad(Self::query_context_pad_fn(&mut found, gl_context));
 
         found
     }
 
-    fn check_context_exists(element: &gst::Element) -> bool {
-        element.context(GST_CONTEXT_GLOW_TYPE).is_some()
+    fn check_context_exists(element: &gst::Element, gl_context: &gst_gl::GLContext) -> bool {
+        element
+            .glow_context()
+            .is_some_and(|glow| glow.is_compatible(gl_context))
     }
 
-    fn query_context_by_message(element: &gst::Element) -> Result<bool, glib::BoolError> {
+    fn query_context_by_message(
+        element: &gst::Element,
+        gl_context: &gst_gl::GLContext,
+    ) -> Result<bool, glib::BoolError> {
         let message = gst::message::NeedContext::builder(GST_CONTEXT_GLOW_TYPE)
-            .src(&*element)
+            .src(element)
             .build();
 
         gst::trace!(CAT, obj = element, "Posting need GLOW context message");
@@ -163,7 +305,71 @@ impl GlowContext {
             return Err(err);
         }
 
-        Ok(element.context(GST_CONTEXT_GLOW_TYPE).is_some())
+        Ok(Self::check_context_exists(element, gl_context))
+    }
+
+    /// Lets the application and the other elements of the pipeline know about a created context
+    fn post_have_context(element: &gst::Element, context: gst::Context) {
+        let message = gst::message::HaveContext::builder(context)
+            .src(element)
+            .build();
+
+        gst::trace!(CAT, obj = element, "Posting have GLOW context message");
+        if let Err(err) = element.post_message(message) {
+            gst::warning!(
+                CAT,
+                obj = element,
+                "Failed to post have context message: {}",
+                err
+            );
+        }
+    }
+
+    /// Finds the glow context of `element` or a nearby element, otherwise creates one for the GL
+    /// context returned by `parent_gl` and shares it with the pipeline
+    ///
+    /// Glow contexts of other GL contexts are ignored, see [`GlowContext::is_compatible`].
+    pub fn find_or_create(
+        element: &gst::Element,
+        parent_gl: impl FnOnce() -> Option<gst_gl::GLContext>,
+    ) -> bool {
+        let Some(parent_gl) = parent_gl() else {
+            gst::error!(CAT, obj = element, "can't find gl context");
+            return false;
+        };
+
+        if Self::check_context_exists(element, &parent_gl) {
+            return true;
+        }
+
+        match Self::query_context_from_nearby_elements(element, &parent_gl) {
+            Ok(true) => {
+                gst::info!(CAT, obj = element, "found glow context in nearby element");
+                return true;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                gst::error!(
+                    CAT,
+                    obj = element,
+                    "failed to query for glow context: {}",
+                    err
+                );
+            }
+        }
+
+        // Creating one, make sure the control flow returns if Glow context available!
+
+        let Some(glow_context) = Self::for_gl_context(&parent_gl) else {
+            gst::error!(CAT, obj = element, "failed to create GLOW context");
+            return false;
+        };
+
+        let gst_glow_context = glow_context.as_gst_context();
+        element.set_context(&gst_glow_context);
+        Self::post_have_context(element, gst_glow_context);
+
+        true
     }
 
     pub fn map_gst_context_to_glow(context: gst::Context) -> Option<GlowContext> {
@@ -172,21 +378,18 @@ impl GlowContext {
         }
 
         let structure = context.structure();
-        let glow_ctx = match structure.get::<GlowContext>(GLOW_CONTEXT_FIELD).ok() {
-            Some(ctx) => ctx,
-            None => return None,
-        };
-        Some(glow_ctx)
+        structure.get::<GlowContext>(GLOW_CONTEXT_FIELD).ok()
     }
 
     pub fn query_context_from_nearby_elements(
         element: &gst::Element,
+        gl_context: &gst_gl::GLContext,
     ) -> Result<bool, glib::BoolError> {
-        if Self::query_context_from_pads(element) {
+        if Self::query_context_from_pads(element, gl_context) {
             return Ok(true);
         }
 
-        if Self::query_context_by_message(element)? {
+        if Self::query_context_by_message(element, gl_context)? {
             return Ok(true);
         }
 
@@ -232,6 +435,38 @@ mod imp {
 
     impl ObjectImpl for GlowContext {}
 
+    /// Moves the glow context to the GL thread for releasing the debug hook
+    struct SendContext(glow::Context);
+
+    unsafe impl Send for SendContext {}
+
+    impl SendContext {
+        /// Must be called on the GL thread of `parent`
+        fn release(self, parent: &gst_gl::GLContext) {
+            unsafe { debug_hook::release(&self.0, parent) };
+        }
+    }
+
+    impl Drop for GlowContext {
+        fn drop(&mut self) {
+            let Some(Inner {
+                parent_context,
+                context,
+            }) = self.inner.get_mut().take()
+            else {
+                return;
+            };
+
+            // The debug hook is released with the context current
+            let context = SendContext(context);
+            if gst_gl::GLContext::current().as_ref() == Some(&parent_context) {
+                context.release(&parent_context);
+                return;
+            }
+            parent_context.thread_add(move |ctx| context.release(ctx));
+        }
+    }
+
     unsafe impl Send for GlowContext {}
     unsafe impl Sync for GlowContext {}
 }
@@ -256,51 +491,72 @@ pub trait GstElementFindGlowContextExt {
 
 impl GstElementFindGlowContextExt for GLBaseFilter {
     fn find_glow_context(&self) -> bool {
-        if GlowContext::check_context_exists(self.upcast_ref()) {
-            return true;
-        }
-
-        match GlowContext::query_context_from_nearby_elements(self.upcast_ref()) {
-            Ok(true) => {
-                gst::info!(CAT, obj = self, "found glow context in nearby element");
-                return true;
+        GlowContext::find_or_create(self.upcast_ref(), || {
+            if !GLBaseFilterExt::find_gl_context(self) {
+                return None;
             }
-            Ok(false) => {}
-            Err(err) => {
-                gst::error!(CAT, obj = self, "failed to query for glow context: {}", err);
+            GLBaseFilterExt::context(self)
+        })
+    }
+}
+
+/// The source finds its GL context before `gl_start`, so this must be called from there or later
+impl GstElementFindGlowContextExt for GLBaseSrc {
+    fn find_glow_context(&self) -> bool {
+        GlowContext::find_or_create(self.upcast_ref(), || {
+            let _lock = self.object_lock();
+            // SAFETY: the context is a public field of the instance struct, guarded by the object
+            // lock
+            unsafe {
+                let src = self.as_ptr() as *const gst_gl::ffi::GstGLBaseSrc;
+                from_glib_none((*src).context)
             }
-        }
+        })
+    }
+}
 
-        // Creating one, make sure the control flow returns if Glow context available!
+/// Takes the GL context from the `context` property, which GL mixers and sinks expose once they
+/// found it
+///
+/// Elements keeping their GL context elsewhere can call [`GlowContext::find_or_create`] instead.
+impl GstElementFindGlowContextExt for gst::Element {
+    fn find_glow_context(&self) -> bool {
+        GlowContext::find_or_create(self, || {
+            let pspec = self.find_property("context")?;
+            if !pspec.value_type().is_a(gst_gl::GLContext::static_type())
+                || !pspec.flags().contains(glib::ParamFlags::READABLE)
+            {
+                return None;
+            }
+            self.property::<Option<gst_gl::GLContext>>("context")
+        })
+    }
+}
 
-        if !GLBaseFilterExt::find_gl_context(self) {
-            gst::error!(CAT, obj = self, "can't find gl context");
-            return false;
-        }
+pub trait GstElementHandleGlowContextQueryExt {
+    /// Answers a context query for the glow context with the one set on the element, returns
+    /// `false` for other queries and when there is no glow context yet
+    fn handle_glow_context_query(&self, query: &mut gst::QueryRef) -> bool;
+}
 
-        let Some(parent_gl) = GLBaseFilterExt::context(self) else {
-            gst::error!(
-                CAT,
-                obj = self,
-                "can't find gl context, even when find success"
-            );
+impl<T> GstElementHandleGlowContextQueryExt for T
+where
+    T: ElementExt,
+{
+    fn handle_glow_context_query(&self, query: &mut gst::QueryRef) -> bool {
+        let gst::QueryViewMut::Context(query) = query.view_mut() else {
             return false;
         };
+        if query.context_type() != GST_CONTEXT_GLOW_TYPE {
+            return false;
+        }
 
-        let mut new_context = None;
-        parent_gl.thread_add(|ctx| {
-            // Should be safe to call this in GL thread
-            let glow = unsafe { GlowContext::new(ctx) };
-            new_context = Some(glow);
-        });
-
-        let Some(glow_context) = new_context else {
-            gst::error!(CAT, obj = self, "failed to create GLOW context");
+        let Some(context) = self.context(GST_CONTEXT_GLOW_TYPE) else {
             return false;
         };
 
-        let gst_glow_context = glow_context.as_gst_context();
-        self.set_context(&gst_glow_context);
+        gst::debug!(CAT, obj = self, "Answering GLOW context query");
+        query.set_context(&context);
 
         true
     }
@@ -3,6 +3,13 @@
 //!
 
 use std::num::NonZeroU32;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use glib::translate::*;
+use gst_gl::prelude::*;
+
+use crate::glib;
 
 pub trait AsGlowProgram {
     fn as_glow_program(&self) -> Option<glow::NativeProgram>;
@@ -35,3 +42,145 @@ impl AsGlowTexture for gst_gl::GLMemoryRef {
         NonZeroU32::new(handle).map(glow::NativeTexture)
     }
 }
+
+pub trait AsGlowFramebuffer {
+    fn as_glow_framebuffer(&self) -> Option<glow::NativeFramebuffer>;
+}
+
+impl AsGlowFramebuffer for gst_gl::GLFramebuffer {
+    fn as_glow_framebuffer(&self) -> Option<glow::NativeFramebuffer> {
+        NonZeroU32::new(self.id()).map(glow::NativeFramebuffer)
+    }
+}
+
+pub trait AsGlowBuffer {
+    fn as_glow_buffer(&self) -> Option<glow::NativeBuffer>;
+}
+
+/// GstGLBuffer has no binding, so the buffer is taken from any memory allocated by the GL buffer
+/// allocator, other memory gives `None`
+impl AsGlowBuffer for gst::MemoryRef {
+    fn as_glow_buffer(&self) -> Option<glow::NativeBuffer> {
+        // SAFETY: the memory is only read as GstGLBuffer after checking its allocator
+        unsafe {
+            let mem = self.as_mut_ptr();
+            if !from_glib::<_, bool>(gst_gl::ffi::gst_is_gl_buffer(mem)) {
+                return None;
+            }
+            let buffer = &*(mem as *const gst_gl::ffi::GstGLBuffer);
+            NonZeroU32::new(buffer.id).map(glow::NativeBuffer)
+        }
+    }
+}
+
+pub trait AsGlowShader {
+    fn as_glow_shader(&self) -> Option<glow::NativeShader>;
+}
+
+impl AsGlowShader for gst_gl::GLSLStage {
+    fn as_glow_shader(&self) -> Option<glow::NativeShader> {
+        NonZeroU32::new(self.handle()).map(glow::NativeShader)
+    }
+}
+
+pub trait AsGlowFence {
+    fn as_glow_fence(&self) -> Option<glow::NativeFence>;
+}
+
+/// The fence exists after `set_sync_point` on a context supporting sync objects, other contexts
+/// fall back to `glFinish` and have no fence
+impl AsGlowFence for gst_gl::GLSyncMeta {
+    fn as_glow_fence(&self) -> Option<glow::NativeFence> {
+        // SAFETY: GLSyncMeta is a transparent wrapper of GstGLSyncMeta
+        let meta = unsafe { &*(self as *const Self as *const gst_gl::ffi::GstGLSyncMeta) };
+        (!meta.data.is_null()).then(|| glow::NativeFence(meta.data.cast()))
+    }
+}
+
+/// Reverse transition of [`AsGlowTexture`] for textures created with glow
+pub trait IntoGLMemory {
+    /// Wraps the 2D texture as memory of `plane` of `info`, so it can be pushed downstream in a
+    /// buffer
+    ///
+    /// GStreamer never deletes the texture, `release` is called once the memory is freed, or
+    /// before returning when wrapping fails, and may run on any thread.
+    ///
+    /// # Safety
+    /// The texture must belong to `context`, or a context shared with it, match the size and
+    /// format of the plane and stay alive until `release` is called.
+    unsafe fn into_gl_memory<F: FnOnce() + Send + 'static>(
+        self,
+        context: &gst_gl::GLContext,
+        info: &gst_video::VideoInfo,
+        plane: u32,
+        release: F,
+    ) -> Result<gst_gl::GLMemory, glib::BoolError>;
+}
+
+impl IntoGLMemory for glow::NativeTexture {
+    unsafe fn into_gl_memory<F: FnOnce() + Send + 'static>(
+        self,
+        context: &gst_gl::GLContext,
+        info: &gst_video::VideoInfo,
+        plane: u32,
+        release: F,
+    ) -> Result<gst_gl::GLMemory, glib::BoolError> {
+        /// Taken by whoever runs it first, the notify of the memory or the failure paths below
+        type Release<F> = Mutex<Option<F>>;
+
+        unsafe extern "C" fn release_trampoline<F: FnOnce() + Send + 'static>(
+            data: glib::ffi::gpointer,
+        ) {
+            let release = Arc::from_raw(data as *const Release<F>);
+            let release = release.lock().unwrap().take();
+            if let Some(release) = release {
+                release();
+            }
+        }
+
+        let format = gst_gl::GLFormat::from_video_info(context, info, plane);
+        let release = Arc::new(Mutex::new(Some(release)));
+        let data = Arc::into_raw(release.clone());
+        let params = gst_gl::ffi::gst_gl_video_allocation_params_new_wrapped_texture(
+            context.to_glib_none().0,
+            ptr::null(),
+            info.to_glib_none().0,
+            plane,
+            ptr::null(),
+            gst_gl::GLTextureTarget::_2d.into_glib(),
+            format.into_glib(),
+            self.0.get(),
+            data as glib::ffi::gpointer,
+            Some(release_trampoline::<F>),
+        );
+        if params.is_null() {
+            // Nothing kept the notify
+            release_trampoline::<F>(data as glib::ffi::gpointer);
+            return Err(glib::bool_error!(
+                "Invalid allocation params of plane {plane}"
+            ));
+        }
+
+        let allocator = gst_gl::ffi::gst_gl_memory_allocator_get_default(context.to_glib_none().0);
+        let mem = gst_gl::ffi::gst_gl_base_memory_alloc(
+            allocator as *mut gst_gl::ffi::GstGLBaseMemoryAllocator,
+            params as *mut gst_gl::ffi::GstGLAllocationParams,
+        );
+        gst_gl::ffi::gst_gl_allocation_params_free(
+            params as *mut gst_gl::ffi::GstGLAllocationParams,
+        );
+        gst::ffi::gst_object_unref(allocator as *mut gst::ffi::GstObject);
+
+        if mem.is_null() {
+            // A memory freed while failing may have run the notify already, otherwise the notify
+            // data is leaked as nobody can tell whether it is still referenced
+            let release = release.lock().unwrap().take();
+            if let Some(release) = release {
+                release();
+            }
+            return Err(glib::bool_error!("Failed to wrap texture {self:?}"));
+        }
+
+        Ok(from_glib_full(mem as *mut gst_gl::ffi::GstGLMemory))
+    }
+}
@@ -3,14 +3,19 @@ extern crate gstreamer_base as gst_base;
 extern crate gstreamer_gl as gst_gl;
 extern crate gstreamer_video as gst_video;
 
+mod cpu_canny;
+mod cpu_common;
+mod cpu_convolve;
 mod cpu_sobel;
-mod glow_gst_inteop;
+pub mod glow_gst_inteop;
 mod ogl;
 
 use gst::glib;
 
 fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
     cpu_sobel::register(plugin)?;
+    cpu_convolve::register(plugin)?;
+    cpu_canny::register(plugin)?;
     ogl::register(plugin)?;
     Ok(())
 }
@@ -1,8 +1,42 @@
+mod glblur;
+mod glcomputesobel;
+mod glfilter;
+pub(crate) mod glow_filter;
 mod glsobel;
+pub(crate) mod quad;
+pub(crate) mod render_graph;
+
+use gst_gl::prelude::*;
+use gst_gl::GLAPI;
 
 use crate::glib;
 
 pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
     glsobel::register(plugin)?;
+    glfilter::register(plugin)?;
+    glblur::register(plugin)?;
+    glcomputesobel::register(plugin)?;
     Ok(())
 }
+
+/// Version line of shaders written against GLSL ES 3.00 for `context`, `None` without GLES 3.0 or
+/// GL 3.2
+///
+/// Desktop GLSL 1.50 and later compile the same sources, precision qualifiers have no effect
+/// there.
+pub(crate) fn glsl_version(context: &gst_gl::GLContext) -> Option<&'static str> {
+    if context.gl_api().contains(GLAPI::GLES2) {
+        return context
+            .check_gl_version(GLAPI::GLES2, 3, 0)
+            .then_some("#version 300 es\n");
+    }
+
+    let desktop = GLAPI::OPENGL | GLAPI::OPENGL3;
+    if context.check_gl_version(desktop, 3, 3) {
+        Some("#version 330\n")
+    } else if context.check_gl_version(desktop, 3, 2) {
+        Some("#version 150\n")
+    } else {
+        None
+    }
+}
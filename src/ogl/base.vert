@@ -1,4 +1,4 @@
-#version 300 es
+// Version is prepended at runtime to match the fragment shader
 
 precision highp float;
 
@@ -0,0 +1,27 @@
+// Version is prepended at runtime
+
+precision highp float;
+
+in vec2 v_texcoord;
+out vec4 outColor;
+
+// Same as MAX_SIZE of the convolution kernels
+#define MAX_TAPS 15
+
+uniform sampler2D tex;
+// Distance between taps in texture coordinates, along one axis
+uniform vec2 step;
+uniform int radius;
+// Weights of taps from -radius to radius
+uniform float weights[MAX_TAPS];
+
+void main()
+{
+    vec4 col = vec4(0.0);
+    for (int i = -radius; i <= radius; i++)
+    {
+        col += texture(tex, v_texcoord + float(i) * step) * weights[i + radius];
+    }
+
+    outColor = col;
+}
@@ -0,0 +1,276 @@
+use gst::glib;
+use gst::prelude::*;
+
+use crate::ogl::glow_filter::GlowFilter;
+
+glib::wrapper! {
+    pub struct GlBlur(ObjectSubclass<imp::GlBlur>) @extends GlowFilter, gst_gl::GLFilter, gst_gl::GLBaseFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekaglblur",
+        gst::Rank::NONE,
+        GlBlur::static_type(),
+    )
+}
+
+mod imp {
+    use std::sync::{LazyLock, Mutex};
+
+    use gst::{
+        glib::subclass::{object::ObjectImpl, types::ObjectSubclass},
+        prelude::*,
+        subclass::prelude::*,
+    };
+    use gst_base::subclass::{prelude::BaseTransformImpl, BaseTransformMode};
+    use gst_gl::{
+        subclass::{
+            prelude::{GLBaseFilterImpl, GLBaseFilterImplExt, GLFilterImpl},
+            GLFilterMode,
+        },
+        GLSLStage, GLShader,
+    };
+
+    use crate::cpu_common::kernel::Kernel;
+    use crate::glib;
+    use crate::glow_gst_inteop::GlowContext;
+    use crate::ogl::glow_filter::{GlowFilterExt, GlowFilterImpl};
+    use crate::ogl::render_graph::{Pass, RenderGraph, Source};
+
+    static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+        gst::DebugCategory::new(
+            "dekaglblur",
+            gst::DebugColorFlags::empty(),
+            Some("Deka's GL plugin for separable Gaussian blur"),
+        )
+    });
+
+    const DEFAULT_SIGMA: f64 = 1.4;
+
+    /// Names of the passes, each one blurs along one axis
+    const HORIZONTAL: &str = "horizontal";
+    const VERTICAL: &str = "vertical";
+
+    #[derive(Debug, Clone)]
+    struct Settings {
+        sigma: f64,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                sigma: DEFAULT_SIGMA,
+            }
+        }
+    }
+
+    /// Created on the GL thread in `gl_start`
+    struct State {
+        glow: GlowContext,
+        graph: RenderGraph,
+    }
+
+    pub struct GlBlur {
+        settings: Mutex<Settings>,
+        state: Mutex<Option<State>>,
+    }
+
+    impl GlBlur {
+        /// Compiles the one-dimensional blur shader, must be called on the GL thread
+        fn compile_shader(&self, ctx: &gst_gl::GLContext) -> Result<GLShader, gst::LoggableError> {
+            let Some(version) = crate::ogl::glsl_version(ctx) else {
+                return Err(gst::loggable_error!(CAT, "GLES 3.0 or GL 3.2 is required"));
+            };
+
+            let vert_stage = GLSLStage::new(ctx, glow::VERTEX_SHADER);
+            vert_stage.set_strings(
+                gst_gl::GLSLVersion::None,
+                gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
+                &[version, include_str!("base.vert")],
+            )?;
+            if let Err(err) = vert_stage.compile() {
+                return Err(gst::loggable_error!(CAT, "Vert compile error: {err}"));
+            }
+            let frag_stage = GLSLStage::new(ctx, glow::FRAGMENT_SHADER);
+            frag_stage.set_strings(
+                gst_gl::GLSLVersion::None,
+                gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
+                &[version, include_str!("glblur.frag")],
+            )?;
+            if let Err(err) = frag_stage.compile() {
+                return Err(gst::loggable_error!(CAT, "Frag compile error: {err}"));
+            }
+
+            let shader = GLShader::new(ctx);
+            shader.attach(&vert_stage)?;
+            shader.attach(&frag_stage)?;
+            if let Err(err) = shader.link() {
+                return Err(gst::loggable_error!(CAT, "Link error: {err}"));
+            }
+
+            Ok(shader)
+        }
+
+        /// Horizontal pass into an intermediate texture and vertical pass into the output, must be
+        /// called on the GL thread
+        fn build_graph(&self, ctx: &gst_gl::GLContext) -> Result<RenderGraph, gst::LoggableError> {
+            let shader = self.compile_shader(ctx)?;
+            let graph = RenderGraph::new(vec![
+                Pass::new(HORIZONTAL, &shader).input("tex", Source::Input),
+                Pass::new(VERTICAL, &shader).input("tex", Source::Pass(HORIZONTAL.to_owned())),
+            ])?;
+
+            Ok(graph)
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for GlBlur {
+        const NAME: &'static str = "GstDekaGlBlur";
+        type Type = super::GlBlur;
+        type ParentType = super::GlowFilter;
+
+        fn with_class(_klass: &Self::Class) -> Self {
+            Self {
+                settings: Mutex::new(Settings::default()),
+                state: Mutex::new(None),
+            }
+        }
+    }
+
+    impl ObjectImpl for GlBlur {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                vec![glib::ParamSpecDouble::builder("sigma")
+                    .nick("Sigma")
+                    .blurb("Standard deviation of the Gaussian in pixels")
+                    .minimum(0.1)
+                    .maximum(2.5)
+                    .default_value(DEFAULT_SIGMA)
+                    .mutable_playing()
+                    .build()]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let mut settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "sigma" => {
+                    let sigma = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing sigma from {} to {}",
+                        settings.sigma,
+                        sigma
+                    );
+                    settings.sigma = sigma;
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "sigma" => settings.sigma.to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+
+    impl GstObjectImpl for GlBlur {}
+
+    impl ElementImpl for GlBlur {
+        fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+            static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> =
+                LazyLock::new(|| {
+                    gst::subclass::ElementMetadata::new(
+                        "Deka's GL Gaussian blur in rust sample",
+                        "Filter/Effect/Video",
+                        "Blurs image in horizontal and vertical passes",
+                        "Deka <speedcrash100@ya.ru>",
+                    )
+                });
+            Some(&*ELEMENT_METADATA)
+        }
+    }
+
+    impl BaseTransformImpl for GlBlur {
+        const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+        const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+        const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+    }
+
+    impl GLBaseFilterImpl for GlBlur {
+        fn gl_start(&self) -> Result<(), gst::LoggableError> {
+            // The base class finds the glow context
+            self.parent_gl_start()?;
+            let glow = self.obj().glow().expect("found by the base class");
+
+            let graph = self
+                .build_graph(glow.gst_gl_context())
+                .inspect_err(|_| self.parent_gl_stop())?;
+            *self.state.lock().unwrap() = Some(State { glow, graph });
+
+            Ok(())
+        }
+
+        fn gl_stop(&self) {
+            if let Some(mut state) = self.state.lock().unwrap().take() {
+                state.graph.release(state.glow.glow());
+            }
+            self.parent_gl_stop()
+        }
+    }
+
+    impl GLFilterImpl for GlBlur {
+        const MODE: GLFilterMode = GLFilterMode::Texture;
+
+        fn filter_texture(
+            &self,
+            input: &gst_gl::GLMemory,
+            output: &gst_gl::GLMemory,
+        ) -> Result<(), gst::LoggableError> {
+            let mut state_lock = self.state.lock().unwrap();
+            let Some(state) = &mut *state_lock else {
+                return Err(gst::loggable_error!(CAT, "Shader is not loaded"));
+            };
+
+            let sigma = self.settings.lock().unwrap().sigma;
+            let gaussian =
+                Kernel::gaussian(sigma).map_err(|err| gst::loggable_error!(CAT, "{err}"))?;
+            let Some((column, row)) = gaussian.separate() else {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "Gaussian of sigma {sigma} is not separable"
+                ));
+            };
+            let (width, height) = (input.texture_width(), input.texture_height());
+
+            let obj = self.obj();
+            let rendered = obj.capture_gl_errors(|| {
+                state
+                    .graph
+                    .run(obj.upcast_ref(), &state.glow, input, output, |pass| {
+                        let (weights, step) = match pass.name.as_str() {
+                            HORIZONTAL => (&row, (1.0 / width as f32, 0.0)),
+                            _ => (&column, (0.0, 1.0 / height as f32)),
+                        };
+                        pass.shader.set_uniform_2f("step", step.0, step.1);
+                        pass.shader
+                            .set_uniform_1i("radius", (weights.len() / 2) as i32);
+                        pass.shader.set_uniform_1fv("weights", weights);
+                    })
+            })?;
+            rendered?;
+
+            Ok(())
+        }
+    }
+
+    impl GlowFilterImpl for GlBlur {}
+}
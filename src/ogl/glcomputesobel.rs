@@ -0,0 +1,492 @@
+use gst::glib;
+use gst::prelude::*;
+
+use crate::ogl::glow_filter::GlowFilter;
+
+glib::wrapper! {
+    pub struct GlComputeSobel(ObjectSubclass<imp::GlComputeSobel>) @extends GlowFilter, gst_gl::GLFilter, gst_gl::GLBaseFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekaglcomputesobel",
+        gst::Rank::NONE,
+        GlComputeSobel::static_type(),
+    )
+}
+
+mod imp {
+    use std::sync::{LazyLock, Mutex};
+
+    use glow::HasContext;
+
+    use gst::{
+        glib::subclass::{object::ObjectImpl, types::ObjectSubclass},
+        prelude::*,
+        subclass::prelude::*,
+    };
+    use gst_base::subclass::{prelude::BaseTransformImpl, BaseTransformMode};
+    use gst_gl::{
+        prelude::GLContextExt,
+        subclass::{
+            prelude::{GLBaseFilterImpl, GLBaseFilterImplExt, GLFilterImpl},
+            GLFilterMode,
+        },
+        GLAPI,
+    };
+
+    use crate::cpu_common::kernel::{self, Kernel};
+    use crate::glib;
+    use crate::glow_gst_inteop::prelude::*;
+    use crate::glow_gst_inteop::GlowContext;
+    use crate::ogl::glow_filter::{compile_program, GlowFilterExt, GlowFilterImpl};
+
+    static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+        gst::DebugCategory::new(
+            "dekaglcomputesobel",
+            gst::DebugColorFlags::empty(),
+            Some("Deka's GL plugin for Sobel edge detection in a compute shader"),
+        )
+    });
+
+    /// Sobel-Y, positive when the intensity grows upwards
+    const DEFAULT_KERNEL: [f32; 9] = [
+        1.0, 2.0, 1.0, //
+        0.0, 0.0, 0.0, //
+        -1.0, -2.0, -1.0, //
+    ];
+    const DEFAULT_SCALE: f64 = 1.0;
+    const DEFAULT_BIAS: f64 = 0.0;
+
+    /// Must match `local_size_x` and `local_size_y` of the compute shader
+    const WORKGROUP_SIZE: u32 = 8;
+
+    #[derive(Debug, Clone)]
+    struct Settings {
+        kernel: Kernel,
+        scale: f64,
+        bias: f64,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                kernel: Kernel::new(3, 3, DEFAULT_KERNEL.to_vec()).expect("valid kernel"),
+                scale: DEFAULT_SCALE,
+                bias: DEFAULT_BIAS,
+            }
+        }
+    }
+
+    /// Texture with immutable storage, the only kind GLES binds as image
+    struct StorageTexture {
+        texture: glow::NativeTexture,
+        size: (u32, u32),
+    }
+
+    /// Textures the images are bound to on GLES, which can't bind the mutable textures of
+    /// `GLMemory` as images
+    ///
+    /// The input is copied in before the dispatch and the output copied out after it.
+    struct Scratch {
+        input: Option<StorageTexture>,
+        output: Option<StorageTexture>,
+        /// Read framebuffer of the copies
+        framebuffer: glow::NativeFramebuffer,
+    }
+
+    impl Scratch {
+        fn new(gl: &glow::Context) -> Result<Self, String> {
+            Ok(Self {
+                input: None,
+                output: None,
+                framebuffer: unsafe { gl.create_framebuffer()? },
+            })
+        }
+
+        /// Texture of `slot`, recreated when it does not have `size`
+        fn texture(
+            gl: &glow::Context,
+            slot: &mut Option<StorageTexture>,
+            size: (u32, u32),
+        ) -> Result<glow::NativeTexture, String> {
+            if let Some(storage) = slot.as_ref().filter(|storage| storage.size == size) {
+                return Ok(storage.texture);
+            }
+
+            unsafe {
+                if let Some(storage) = slot.take() {
+                    gl.delete_texture(storage.texture);
+                }
+
+                let texture = gl.create_texture()?;
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                gl.tex_storage_2d(
+                    glow::TEXTURE_2D,
+                    1,
+                    glow::RGBA8,
+                    size.0 as i32,
+                    size.1 as i32,
+                );
+                gl.bind_texture(glow::TEXTURE_2D, None);
+                *slot = Some(StorageTexture { texture, size });
+
+                Ok(texture)
+            }
+        }
+
+        /// Copies `size` texels of `source` into `target`
+        unsafe fn copy(
+            &self,
+            gl: &glow::Context,
+            source: glow::NativeTexture,
+            target: glow::NativeTexture,
+            size: (u32, u32),
+        ) {
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::READ_FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(source),
+                0,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, Some(target));
+            gl.copy_tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                0,
+                0,
+                0,
+                size.0 as i32,
+                size.1 as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.framebuffer_texture_2d(
+                glow::READ_FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                None,
+                0,
+            );
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+        }
+
+        fn delete(self, gl: &glow::Context) {
+            unsafe {
+                for storage in [self.input, self.output].into_iter().flatten() {
+                    gl.delete_texture(storage.texture);
+                }
+                gl.delete_framebuffer(self.framebuffer);
+            }
+        }
+    }
+
+    /// Created on the GL thread in `gl_start`
+    struct State {
+        glow: GlowContext,
+        program: glow::NativeProgram,
+        /// Only used on GLES
+        scratch: Option<Scratch>,
+    }
+
+    pub struct GlComputeSobel {
+        settings: Mutex<Settings>,
+        state: Mutex<Option<State>>,
+    }
+
+    impl GlComputeSobel {
+        /// Compiles and links the compute shader, must be called on the GL thread
+        fn build_program(
+            &self,
+            glow: &GlowContext,
+        ) -> Result<glow::NativeProgram, gst::LoggableError> {
+            let ctx = glow.gst_gl_context();
+            let version = if ctx.gl_api().contains(GLAPI::GLES2) {
+                if !ctx.check_gl_version(GLAPI::GLES2, 3, 1) {
+                    return Err(gst::loggable_error!(CAT, "Compute shaders need GLES 3.1"));
+                }
+                "#version 310 es\n"
+            } else {
+                if !ctx.check_gl_version(GLAPI::OPENGL3, 4, 3) {
+                    return Err(gst::loggable_error!(CAT, "Compute shaders need GL 4.3"));
+                }
+                "#version 430\n"
+            };
+            let source = [version, include_str!("glcomputesobel.comp")].concat();
+
+            compile_program(glow.glow(), &[(glow::COMPUTE_SHADER, &source)])
+                .map_err(|log| gst::loggable_error!(CAT, "Failed to build program: {log}"))
+        }
+
+        /// Builds the program and on GLES the scratch textures, must be called on the GL thread
+        fn create_state(&self, glow: GlowContext) -> Result<State, gst::LoggableError> {
+            let program = self.build_program(&glow)?;
+            let scratch = if glow.gst_gl_context().gl_api().contains(GLAPI::GLES2) {
+                match Scratch::new(glow.glow()) {
+                    Ok(scratch) => Some(scratch),
+                    Err(err) => {
+                        unsafe { glow.glow().delete_program(program) };
+                        return Err(gst::loggable_error!(
+                            CAT,
+                            "Failed to create framebuffer: {err}"
+                        ));
+                    }
+                }
+            } else {
+                None
+            };
+
+            Ok(State {
+                glow,
+                program,
+                scratch,
+            })
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for GlComputeSobel {
+        const NAME: &'static str = "GstDekaGlComputeSobel";
+        type Type = super::GlComputeSobel;
+        type ParentType = super::GlowFilter;
+
+        fn with_class(_klass: &Self::Class) -> Self {
+            Self {
+                settings: Mutex::new(Settings::default()),
+                state: Mutex::new(None),
+            }
+        }
+    }
+
+    impl ObjectImpl for GlComputeSobel {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                vec![
+                    kernel::param_spec("Array of 3 kernel rows of 3 coefficients"),
+                    glib::ParamSpecDouble::builder("scale")
+                        .nick("Scale")
+                        .blurb("Value the weighted sum is multiplied by")
+                        .default_value(DEFAULT_SCALE)
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecDouble::builder("bias")
+                        .nick("Bias")
+                        .blurb("Value added to the result after scaling, 1.0 is the full range")
+                        .default_value(DEFAULT_BIAS)
+                        .mutable_playing()
+                        .build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let mut settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "kernel" => match Kernel::from_property(value, Some(3)) {
+                    Ok(kernel) => {
+                        gst::info!(
+                            CAT,
+                            imp = self,
+                            "Changing kernel from {:?} to {:?}",
+                            settings.kernel,
+                            kernel
+                        );
+                        settings.kernel = kernel;
+                    }
+                    Err(err) => {
+                        gst::error!(CAT, imp = self, "Invalid kernel {:?}: {}", value, err);
+                    }
+                },
+                "scale" => {
+                    let scale = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing scale from {} to {}",
+                        settings.scale,
+                        scale
+                    );
+                    settings.scale = scale;
+                }
+                "bias" => {
+                    let bias = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing bias from {} to {}",
+                        settings.bias,
+                        bias
+                    );
+                    settings.bias = bias;
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "kernel" => settings.kernel.to_array().to_value(),
+                "scale" => settings.scale.to_value(),
+                "bias" => settings.bias.to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+
+    impl GstObjectImpl for GlComputeSobel {}
+
+    impl ElementImpl for GlComputeSobel {
+        fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+            static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> =
+                LazyLock::new(|| {
+                    gst::subclass::ElementMetadata::new(
+                        "Deka's GL compute sobel filter in rust sample",
+                        "Filter/Effect/Video",
+                        "Applies sobel kernel to image in a compute shader",
+                        "Deka <speedcrash100@ya.ru>",
+                    )
+                });
+            Some(&*ELEMENT_METADATA)
+        }
+    }
+
+    impl BaseTransformImpl for GlComputeSobel {
+        const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+        const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+        const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+    }
+
+    impl GLBaseFilterImpl for GlComputeSobel {
+        fn gl_start(&self) -> Result<(), gst::LoggableError> {
+            // The base class finds the glow context
+            self.parent_gl_start()?;
+            let glow = self.obj().glow().expect("found by the base class");
+
+            let state = self
+                .create_state(glow)
+                .inspect_err(|_| self.parent_gl_stop())?;
+            *self.state.lock().unwrap() = Some(state);
+
+            Ok(())
+        }
+
+        fn gl_stop(&self) {
+            if let Some(State {
+                glow,
+                program,
+                scratch,
+            }) = self.state.lock().unwrap().take()
+            {
+                glow.with_glow(|gl| {
+                    if let Some(scratch) = scratch {
+                        scratch.delete(gl);
+                    }
+                    unsafe { gl.delete_program(program) };
+                });
+            }
+            self.parent_gl_stop()
+        }
+    }
+
+    impl GLFilterImpl for GlComputeSobel {
+        const MODE: GLFilterMode = GLFilterMode::Texture;
+
+        fn filter_texture(
+            &self,
+            input: &gst_gl::GLMemory,
+            output: &gst_gl::GLMemory,
+        ) -> Result<(), gst::LoggableError> {
+            let mut state_lock = self.state.lock().unwrap();
+            let Some(state) = &mut *state_lock else {
+                return Err(gst::loggable_error!(CAT, "Shader is not loaded"));
+            };
+            let settings = self.settings.lock().unwrap().clone();
+
+            let (Some(input_texture), Some(output_texture)) =
+                (input.as_glow_texture(), output.as_glow_texture())
+            else {
+                return Err(gst::loggable_error!(CAT, "Memory has no texture"));
+            };
+            let input_size = (input.texture_width() as u32, input.texture_height() as u32);
+            let (width, height) = (
+                output.texture_width() as u32,
+                output.texture_height() as u32,
+            );
+
+            let gl = state.glow.glow();
+            let dispatched = self.obj().capture_gl_errors(|| unsafe {
+                let (input_image, output_image) = match &mut state.scratch {
+                    Some(scratch) => {
+                        let input_image = Scratch::texture(gl, &mut scratch.input, input_size)?;
+                        let output_image =
+                            Scratch::texture(gl, &mut scratch.output, (width, height))?;
+                        scratch.copy(gl, input_texture, input_image, input_size);
+                        (input_image, output_image)
+                    }
+                    None => (input_texture, output_texture),
+                };
+
+                gl.use_program(Some(state.program));
+                let uniform = |name: &str| gl.get_uniform_location(state.program, name);
+                gl.uniform_1_f32_slice(uniform("kernel").as_ref(), settings.kernel.coefficients());
+                gl.uniform_1_f32(uniform("scale").as_ref(), settings.scale as f32);
+                gl.uniform_1_f32(uniform("bias").as_ref(), settings.bias as f32);
+
+                // Units match the bindings of the images in the shader
+                gl.bind_image_texture(
+                    0,
+                    Some(input_image),
+                    0,
+                    false,
+                    0,
+                    glow::READ_ONLY,
+                    glow::RGBA8,
+                );
+                gl.bind_image_texture(
+                    1,
+                    Some(output_image),
+                    0,
+                    false,
+                    0,
+                    glow::WRITE_ONLY,
+                    glow::RGBA8,
+                );
+
+                gl.dispatch_compute(
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+
+                // Downstream samples or renders to the output after the stores
+                gl.memory_barrier(
+                    glow::SHADER_IMAGE_ACCESS_BARRIER_BIT
+                        | glow::TEXTURE_FETCH_BARRIER_BIT
+                        | glow::FRAMEBUFFER_BARRIER_BIT,
+                );
+
+                gl.bind_image_texture(0, None, 0, false, 0, glow::READ_ONLY, glow::RGBA8);
+                gl.bind_image_texture(1, None, 0, false, 0, glow::WRITE_ONLY, glow::RGBA8);
+                gl.use_program(None);
+
+                if let Some(scratch) = &state.scratch {
+                    scratch.copy(gl, output_image, output_texture, (width, height));
+                }
+
+                Ok::<_, String>(())
+            })?;
+            dispatched.map_err(|err| {
+                gst::loggable_error!(CAT, "Failed to create image texture: {err}")
+            })?;
+
+            Ok(())
+        }
+    }
+
+    impl GlowFilterImpl for GlComputeSobel {}
+}
@@ -0,0 +1,15 @@
+#version 300 es
+
+// Default fragment shader of dekaglfilter, copies the input
+
+precision highp float;
+
+in vec2 v_texcoord;
+out vec4 outColor;
+
+uniform sampler2D tex;
+
+void main()
+{
+    outColor = texture(tex, v_texcoord);
+}
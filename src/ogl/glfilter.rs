@@ -0,0 +1,530 @@
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+    pub struct GlFilter(ObjectSubclass<imp::GlFilter>) @extends gst_gl::GLFilter, gst_gl::GLBaseFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekaglfilter",
+        gst::Rank::NONE,
+        GlFilter::static_type(),
+    )
+}
+
+mod imp {
+    use std::sync::{LazyLock, Mutex};
+    use std::time::{Duration, Instant, SystemTime};
+
+    use gst::{
+        glib::subclass::{object::ObjectImpl, types::ObjectSubclass},
+        prelude::*,
+        subclass::prelude::*,
+    };
+    use gst_base::subclass::{prelude::BaseTransformImpl, BaseTransformMode};
+    use gst_gl::{
+        prelude::{GLBaseFilterExt, GLFilterExt},
+        subclass::{
+            prelude::{GLBaseFilterImpl, GLBaseFilterImplExt, GLFilterImpl, GLFilterImplExt},
+            GLFilterMode,
+        },
+        GLSLStage, GLShader,
+    };
+
+    use crate::glib;
+
+    static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+        gst::DebugCategory::new(
+            "dekaglfilter",
+            gst::DebugColorFlags::empty(),
+            Some("Deka's GL plugin for custom fragment shaders"),
+        )
+    });
+
+    /// Used for sources without a version line
+    const DEFAULT_VERSION: &str = "#version 300 es\n";
+
+    const DEFAULT_WATCH: bool = false;
+
+    /// How often the watched fragment shader file is checked for changes
+    const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+    #[derive(Debug, Clone, Default)]
+    struct Settings {
+        fragment_location: Option<String>,
+        fragment: Option<String>,
+        uniforms: Option<gst::Structure>,
+        watch: bool,
+    }
+
+    /// Step the shader failed in, decides the domain of the posted message
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ShaderErrorKind {
+        /// Reading the fragment shader file, posted as `ResourceError::OpenRead`
+        Read,
+        /// Building the shader in GL, posted as `LibraryError::Failed`
+        Build,
+    }
+
+    /// Failure to build the shader with the details of the failed step, e.g. the GLSL log
+    #[derive(Debug)]
+    struct ShaderError {
+        kind: ShaderErrorKind,
+        message: String,
+        details: String,
+    }
+
+    impl ShaderError {
+        fn new(
+            kind: ShaderErrorKind,
+            message: impl Into<String>,
+            details: impl std::fmt::Display,
+        ) -> Self {
+            Self {
+                kind,
+                message: message.into(),
+                details: details.to_string(),
+            }
+        }
+    }
+
+    /// Modification time of the fragment shader file the current shader was compiled from
+    struct Watch {
+        modified: Option<SystemTime>,
+        polled: Instant,
+    }
+
+    impl Settings {
+        /// Fragment shader source, the file takes precedence over the string
+        fn fragment_source(&self) -> Result<String, ShaderError> {
+            if let Some(location) = &self.fragment_location {
+                return std::fs::read_to_string(location).map_err(|err| {
+                    ShaderError::new(
+                        ShaderErrorKind::Read,
+                        format!("Failed to read fragment shader {location}"),
+                        err,
+                    )
+                });
+            }
+
+            Ok(self
+                .fragment
+                .clone()
+                .unwrap_or_else(|| include_str!("glfilter.frag").to_owned()))
+        }
+    }
+
+    /// Version line of `source`, the vertex shader must use the same one to be linked with it
+    fn version_line(source: &str) -> Option<&str> {
+        source
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("//"))
+            .filter(|line| line.starts_with("#version"))
+    }
+
+    pub struct GlFilter {
+        settings: Mutex<Settings>,
+        /// Compiled on the first frame after the shader properties change
+        shader: Mutex<Option<GLShader>>,
+        input_size: Mutex<Option<(u32, u32)>>,
+        watch: Mutex<Option<Watch>>,
+    }
+
+    impl GlFilter {
+        /// Modification time of the fragment shader file if there is one
+        fn fragment_modified(&self) -> Option<SystemTime> {
+            let location = self.settings.lock().unwrap().fragment_location.clone()?;
+            std::fs::metadata(location)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        }
+
+        /// Whether the watched fragment shader file changed since the last compilation, polls
+        /// the file at most once per `WATCH_INTERVAL`
+        fn fragment_changed(&self) -> bool {
+            let mut watch = self.watch.lock().unwrap();
+            let Some(watch) = watch.as_mut() else {
+                return false;
+            };
+            if watch.polled.elapsed() < WATCH_INTERVAL {
+                return false;
+            }
+
+            watch.polled = Instant::now();
+            let modified = self.fragment_modified();
+            if modified == watch.modified {
+                return false;
+            }
+
+            // The same modification must not be retried when it fails to compile
+            watch.modified = modified;
+            true
+        }
+
+        /// Compiles the fragment shader of the settings, must be called on the GL thread
+        fn compile_shader(&self) -> Result<GLShader, ShaderError> {
+            let obj = self.obj();
+            let gl_base_filter = obj.upcast_ref::<gst_gl::GLBaseFilter>();
+
+            let Some(ctx) = GLBaseFilterExt::context(gl_base_filter) else {
+                return Err(ShaderError::new(
+                    ShaderErrorKind::Build,
+                    "Cannot find GL context",
+                    "GL context is not set",
+                ));
+            };
+
+            let watch = self.settings.lock().unwrap().watch;
+            let watch = watch.then(|| Watch {
+                modified: self.fragment_modified(),
+                polled: Instant::now(),
+            });
+            *self.watch.lock().unwrap() = watch;
+
+            let fragment = self.settings.lock().unwrap().fragment_source()?;
+            let (version, fragment_version) = match version_line(&fragment) {
+                Some(version) => (format!("{version}\n"), ""),
+                None => (DEFAULT_VERSION.to_owned(), DEFAULT_VERSION),
+            };
+
+            let failed = |what: &str, err: &dyn std::fmt::Display| {
+                ShaderError::new(ShaderErrorKind::Build, format!("Failed to {what}"), err)
+            };
+
+            let vert_stage = GLSLStage::new(&ctx, glow::VERTEX_SHADER);
+            vert_stage
+                .set_strings(
+                    gst_gl::GLSLVersion::None,
+                    gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
+                    &[&version, include_str!("base.vert")],
+                )
+                .map_err(|err| failed("set vertex shader source", &err))?;
+            vert_stage
+                .compile()
+                .map_err(|err| failed("compile vertex shader", &err))?;
+
+            let frag_stage = GLSLStage::new(&ctx, glow::FRAGMENT_SHADER);
+            frag_stage
+                .set_strings(
+                    gst_gl::GLSLVersion::None,
+                    gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
+                    &[fragment_version, &fragment],
+                )
+                .map_err(|err| failed("set fragment shader source", &err))?;
+            frag_stage
+                .compile()
+                .map_err(|err| failed("compile fragment shader", &err))?;
+
+            let shader = GLShader::new(&ctx);
+            shader
+                .attach(&vert_stage)
+                .and_then(|_| shader.attach(&frag_stage))
+                .map_err(|err| failed("attach shader stages", &err))?;
+            shader.link().map_err(|err| failed("link shader", &err))?;
+
+            Ok(shader)
+        }
+
+        /// Posts the failure to build the first shader, the element can't process frames then
+        fn post_shader_error(&self, err: &ShaderError) {
+            match err.kind {
+                ShaderErrorKind::Read => gst::element_imp_error!(
+                    self,
+                    gst::ResourceError::OpenRead,
+                    ("{}", err.message),
+                    ["{}", err.details]
+                ),
+                ShaderErrorKind::Build => gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Failed,
+                    ("{}", err.message),
+                    ["{}", err.details]
+                ),
+            }
+        }
+
+        /// Posts the failure to rebuild a changed shader, the previous one stays in use
+        fn post_shader_warning(&self, err: &ShaderError) {
+            match err.kind {
+                ShaderErrorKind::Read => gst::element_imp_warning!(
+                    self,
+                    gst::ResourceError::OpenRead,
+                    ("{}, keeping the previous shader", err.message),
+                    ["{}", err.details]
+                ),
+                ShaderErrorKind::Build => gst::element_imp_warning!(
+                    self,
+                    gst::LibraryError::Failed,
+                    ("{}, keeping the previous shader", err.message),
+                    ["{}", err.details]
+                ),
+            }
+        }
+
+        /// Sets the fields of `uniforms` as uniforms of the same names on the active program
+        ///
+        /// Numbers and booleans become scalars, arrays of 2 to 4 numbers become vectors
+        fn set_uniforms(&self, shader: &GLShader, uniforms: &gst::StructureRef) {
+            for (name, value) in uniforms.iter() {
+                if let Ok(value) = value.get::<f64>() {
+                    shader.set_uniform_1f(name, value as f32);
+                } else if let Ok(value) = value.get::<f32>() {
+                    shader.set_uniform_1f(name, value);
+                } else if let Ok(value) = value.get::<i32>() {
+                    shader.set_uniform_1i(name, value);
+                } else if let Ok(value) = value.get::<u32>() {
+                    shader.set_uniform_1i(name, value as i32);
+                } else if let Ok(value) = value.get::<bool>() {
+                    shader.set_uniform_1i(name, value as i32);
+                } else if let Ok(array) = value.get::<gst::Array>() {
+                    let components = array
+                        .iter()
+                        .map(|value| {
+                            value
+                                .get::<f64>()
+                                .map(|value| value as f32)
+                                .or_else(|_| value.get::<f32>())
+                                .or_else(|_| value.get::<i32>().map(|value| value as f32))
+                        })
+                        .collect::<Result<Vec<_>, _>>();
+
+                    match components.as_deref() {
+                        Ok(&[x, y]) => shader.set_uniform_2f(name, x, y),
+                        Ok(&[x, y, z]) => shader.set_uniform_3f(name, x, y, z),
+                        Ok(&[x, y, z, w]) => shader.set_uniform_4f(name, x, y, z, w),
+                        _ => gst::warning!(
+                            CAT,
+                            imp = self,
+                            "Uniform {} must have 2 to 4 numbers, got {:?}",
+                            name,
+                            array
+                        ),
+                    }
+                } else {
+                    gst::warning!(
+                        CAT,
+                        imp = self,
+                        "Unsupported type {} of uniform {}",
+                        value.type_(),
+                        name
+                    );
+                }
+            }
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for GlFilter {
+        const NAME: &'static str = "GstDekaGlFilter";
+        type Type = super::GlFilter;
+        type ParentType = gst_gl::GLFilter;
+
+        fn with_class(_klass: &Self::Class) -> Self {
+            Self {
+                settings: Mutex::new(Settings::default()),
+                shader: Mutex::new(None),
+                input_size: Mutex::new(None),
+                watch: Mutex::new(None),
+            }
+        }
+    }
+
+    impl ObjectImpl for GlFilter {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                vec![
+                    glib::ParamSpecString::builder("fragment-location")
+                        .nick("Fragment location")
+                        .blurb("Fragment shader file, takes precedence over fragment")
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecString::builder("fragment")
+                        .nick("Fragment")
+                        .blurb("Fragment shader source, the input is copied if none is set")
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecBoxed::builder::<gst::Structure>("uniforms")
+                        .nick("Uniforms")
+                        .blurb(
+                            "Fields are set as uniforms of the same names, numbers and booleans \
+                             become scalars, arrays of 2 to 4 numbers become vectors",
+                        )
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecBoolean::builder("watch")
+                        .nick("Watch")
+                        .blurb(
+                            "Recompile when the fragment shader file changes, keeping the \
+                             previous shader if the new one fails",
+                        )
+                        .default_value(DEFAULT_WATCH)
+                        .mutable_playing()
+                        .build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let mut settings = self.settings.lock().unwrap();
+            let shader_changed = match pspec.name() {
+                "fragment-location" => {
+                    let location = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing fragment location from {:?} to {:?}",
+                        settings.fragment_location,
+                        location
+                    );
+                    settings.fragment_location = location;
+                    true
+                }
+                "fragment" => {
+                    let fragment = value.get().expect("type checked upstream");
+                    gst::info!(CAT, imp = self, "Changing fragment to {:?}", fragment);
+                    settings.fragment = fragment;
+                    true
+                }
+                "uniforms" => {
+                    let uniforms = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing uniforms from {:?} to {:?}",
+                        settings.uniforms,
+                        uniforms
+                    );
+                    settings.uniforms = uniforms;
+                    false
+                }
+                "watch" => {
+                    let watch = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing watch from {} to {}",
+                        settings.watch,
+                        watch
+                    );
+                    settings.watch = watch;
+                    // Recompiling starts watching the file from its current state
+                    true
+                }
+                _ => unimplemented!(),
+            };
+            drop(settings);
+
+            // Settings are locked while compiling, so the shader is only dropped after unlocking
+            if shader_changed {
+                *self.shader.lock().unwrap() = None;
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "fragment-location" => settings.fragment_location.to_value(),
+                "fragment" => settings.fragment.to_value(),
+                "uniforms" => settings.uniforms.to_value(),
+                "watch" => settings.watch.to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+
+    impl GstObjectImpl for GlFilter {}
+
+    impl ElementImpl for GlFilter {
+        fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+            static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> =
+                LazyLock::new(|| {
+                    gst::subclass::ElementMetadata::new(
+                        "Deka's GL custom shader filter in rust sample",
+                        "Filter/Effect/Video",
+                        "Applies a fragment shader loaded at runtime to image",
+                        "Deka <speedcrash100@ya.ru>",
+                    )
+                });
+            Some(&*ELEMENT_METADATA)
+        }
+    }
+
+    impl BaseTransformImpl for GlFilter {
+        const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+        const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+        const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+    }
+
+    impl GLBaseFilterImpl for GlFilter {
+        fn gl_stop(&self) {
+            *self.shader.lock().unwrap() = None;
+            *self.watch.lock().unwrap() = None;
+            self.parent_gl_stop()
+        }
+    }
+
+    impl GLFilterImpl for GlFilter {
+        const MODE: GLFilterMode = GLFilterMode::Texture;
+
+        fn set_caps(
+            &self,
+            incaps: &gst::Caps,
+            outcaps: &gst::Caps,
+        ) -> Result<(), gst::LoggableError> {
+            let info = gst_video::VideoInfo::from_caps(incaps)
+                .map_err(|_| gst::loggable_error!(CAT, "Invalid input caps {incaps}"))?;
+            *self.input_size.lock().unwrap() = Some((info.width(), info.height()));
+
+            GLFilterImplExt::parent_set_caps(self, incaps, outcaps)
+        }
+
+        fn filter_texture(
+            &self,
+            input: &gst_gl::GLMemory,
+            output: &gst_gl::GLMemory,
+        ) -> Result<(), gst::LoggableError> {
+            let mut shader_lock = self.shader.lock().unwrap();
+
+            if shader_lock.is_none() {
+                gst::debug!(CAT, imp = self, "Compiling shader");
+                match self.compile_shader() {
+                    Ok(shader) => *shader_lock = Some(shader),
+                    Err(err) => {
+                        self.post_shader_error(&err);
+                        return Err(gst::loggable_error!(CAT, "Shader is not loaded"));
+                    }
+                }
+            } else if self.fragment_changed() {
+                gst::info!(CAT, imp = self, "Fragment shader file changed, recompiling");
+                match self.compile_shader() {
+                    Ok(shader) => *shader_lock = Some(shader),
+                    Err(err) => self.post_shader_warning(&err),
+                }
+            }
+            let shader = shader_lock.as_ref().expect("compiled above");
+
+            let Some((width, height)) = *self.input_size.lock().unwrap() else {
+                return Err(gst::loggable_error!(CAT, "Caps are not negotiated"));
+            };
+            let uniforms = self.settings.lock().unwrap().uniforms.clone();
+
+            // Uniforms are set on the active program
+            shader.use_();
+            shader.set_uniform_1f("width", width as f32);
+            shader.set_uniform_1f("height", height as f32);
+            if let Some(uniforms) = &uniforms {
+                self.set_uniforms(shader, uniforms);
+            }
+
+            let obj = self.obj();
+
+            obj.render_to_target_with_shader(input, output, shader);
+
+            Ok(())
+        }
+    }
+}
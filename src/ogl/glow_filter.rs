@@ -0,0 +1,459 @@
+//!
+//! Base class of GL filters written against glow
+//!
+//! The base class finds the glow context in `gl_start` and answers the context queries of other
+//! elements. Subclasses with a [`GlowFilterImpl::FRAGMENT_SHADER`] implement `GLFilterImpl` with
+//! `GLFilterMode::Texture`, the base class builds the program in `gl_start`, deletes it in
+//! `gl_stop` and renders the output with it. Others render on their own in `filter_texture` or
+//! `filter` with the context of [`GlowFilterExt::glow`], after calling `parent_gl_start`.
+//!
+
+use glow::HasContext;
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst_gl::subclass::prelude::GLFilterImpl;
+
+use crate::glow_gst_inteop::GlowContext;
+
+glib::wrapper! {
+    pub struct GlowFilter(ObjectSubclass<imp::GlowFilter>) @extends gst_gl::GLFilter, gst_gl::GLBaseFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+/// Locations the vertex attributes of `base.vert` are bound to
+pub const POSITION_LOCATION: u32 = 0;
+pub const TEXCOORD_LOCATION: u32 = 1;
+
+/// Compiles `stages` of (type, source) and links them into a program, returning the log on
+/// failure
+///
+/// `a_position` and `a_texcoord` are bound to [`POSITION_LOCATION`] and [`TEXCOORD_LOCATION`].
+/// Must be called on the GL thread.
+pub fn compile_program(
+    gl: &glow::Context,
+    stages: &[(u32, &str)],
+) -> Result<glow::NativeProgram, String> {
+    unsafe {
+        let program = gl.create_program()?;
+
+        let mut shaders = Vec::with_capacity(stages.len());
+        let mut result = Ok(());
+        for &(stage, source) in stages {
+            let shader = match gl.create_shader(stage) {
+                Ok(shader) => shader,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            };
+            shaders.push(shader);
+
+            gl.shader_source(shader, source);
+            gl.compile_shader(shader);
+            if !gl.get_shader_compile_status(shader) {
+                result = Err(gl.get_shader_info_log(shader));
+                break;
+            }
+            gl.attach_shader(program, shader);
+        }
+
+        if result.is_ok() {
+            gl.bind_attrib_location(program, POSITION_LOCATION, "a_position");
+            gl.bind_attrib_location(program, TEXCOORD_LOCATION, "a_texcoord");
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                result = Err(gl.get_program_info_log(program));
+            }
+        }
+
+        // The program keeps the compiled stages
+        for shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
+
+        match result {
+            Ok(()) => Ok(program),
+            Err(err) => {
+                gl.delete_program(program);
+                Err(err)
+            }
+        }
+    }
+}
+
+pub trait GlowFilterImpl: GLFilterImpl + ObjectSubclass<Type: IsA<GlowFilter>> {
+    /// Fragment shader without the version line, which is prepended at runtime, `None` for
+    /// subclasses rendering on their own
+    ///
+    /// It gets `v_texcoord` from `base.vert` and the input texture as `sampler2D tex`.
+    const FRAGMENT_SHADER: Option<&'static str> = None;
+
+    /// Prepares rendering `input` into `output`, called on the GL thread with the program in
+    /// use and the output framebuffer bound
+    ///
+    /// The input is bound to texture unit 0, the base class draws the quad afterwards.
+    fn filter_glow(
+        &self,
+        _gl: &glow::Context,
+        _program: glow::NativeProgram,
+        _input: glow::NativeTexture,
+        _output: glow::NativeTexture,
+    ) -> Result<(), gst::LoggableError> {
+        Ok(())
+    }
+}
+
+pub trait GlowFilterExt: IsA<GlowFilter> {
+    /// Glow context found in `gl_start`, `None` before it and after `gl_stop`
+    fn glow(&self) -> Option<GlowContext> {
+        self.upcast_ref::<GlowFilter>().imp().glow()
+    }
+
+    /// Runs `func` and fails when the driver reported GL errors meanwhile and `raise-gl-errors`
+    /// is set, after posting them as an element error. Must be called on the GL thread.
+    fn capture_gl_errors<R>(&self, func: impl FnOnce() -> R) -> Result<R, gst::LoggableError> {
+        self.upcast_ref::<GlowFilter>()
+            .imp()
+            .capture_gl_errors(func)
+    }
+}
+
+impl<O: IsA<GlowFilter>> GlowFilterExt for O {}
+
+type FilterFn = fn(
+    &GlowFilter,
+    &glow::Context,
+    glow::NativeProgram,
+    glow::NativeTexture,
+    glow::NativeTexture,
+) -> Result<(), gst::LoggableError>;
+
+/// Class struct of [`GlowFilter`] with what the subclass provides
+#[repr(C)]
+pub struct GlowFilterClass {
+    parent_class: gst_gl::ffi::GstGLFilterClass,
+    fragment_shader: Option<&'static str>,
+    filter_glow: Option<FilterFn>,
+}
+
+unsafe impl ClassStruct for GlowFilterClass {
+    type Type = imp::GlowFilter;
+}
+
+unsafe impl<T: GlowFilterImpl> IsSubclassable<T> for GlowFilter {
+    fn class_init(klass: &mut glib::Class<Self>) {
+        Self::parent_class_init::<T>(klass);
+        let klass = klass.as_mut();
+        klass.fragment_shader = T::FRAGMENT_SHADER;
+        klass.filter_glow = Some(|obj, gl, program, input, output| {
+            // SAFETY: the class is only initialized for instances of `T::Type`
+            let imp = unsafe { obj.unsafe_cast_ref::<T::Type>() }.imp();
+            imp.filter_glow(gl, program, input, output)
+        });
+    }
+}
+
+mod imp {
+    use std::sync::{LazyLock, Mutex};
+
+    use glow::HasContext;
+    use gst::{
+        glib::subclass::{object::ObjectImpl, types::ObjectSubclass},
+        prelude::*,
+        subclass::prelude::*,
+    };
+    use gst_base::subclass::{
+        prelude::{BaseTransformImpl, BaseTransformImplExt},
+        BaseTransformMode,
+    };
+    use gst_gl::{
+        prelude::GLFilterExt,
+        subclass::{
+            prelude::{GLBaseFilterImpl, GLBaseFilterImplExt, GLFilterImpl},
+            GLFilterMode,
+        },
+    };
+
+    use crate::glib;
+    use crate::glow_gst_inteop::prelude::*;
+    use crate::glow_gst_inteop::{
+        GlowContext, GstElementFindGlowContextExt, GstElementGetGlowContextExt,
+        GstElementHandleGlowContextQueryExt,
+    };
+    use crate::ogl::quad::FullscreenQuad;
+
+    static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+        gst::DebugCategory::new(
+            "dekaglowfilter",
+            gst::DebugColorFlags::empty(),
+            Some("Deka's base class of GL filters using glow"),
+        )
+    });
+
+    const DEFAULT_RAISE_GL_ERRORS: bool = false;
+
+    #[derive(Debug, Clone)]
+    struct Settings {
+        raise_gl_errors: bool,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                raise_gl_errors: DEFAULT_RAISE_GL_ERRORS,
+            }
+        }
+    }
+
+    /// Created on the GL thread in `gl_start`
+    struct State {
+        glow: GlowContext,
+        /// Built from the fragment shader of the subclass if it has one, with the quad it is
+        /// drawn on
+        program: Option<(glow::NativeProgram, FullscreenQuad)>,
+    }
+
+    #[derive(Default)]
+    pub struct GlowFilter {
+        settings: Mutex<Settings>,
+        state: Mutex<Option<State>>,
+    }
+
+    impl GlowFilter {
+        /// Must be called on the GL thread
+        fn build_program(
+            glow: &GlowContext,
+            fragment: &str,
+        ) -> Result<(glow::NativeProgram, FullscreenQuad), gst::LoggableError> {
+            let Some(version) = crate::ogl::glsl_version(glow.gst_gl_context()) else {
+                return Err(gst::loggable_error!(CAT, "GLES 3.0 or GL 3.2 is required"));
+            };
+            let gl = glow.glow();
+            let program = super::compile_program(
+                gl,
+                &[
+                    (
+                        glow::VERTEX_SHADER,
+                        &[version, include_str!("base.vert")].concat(),
+                    ),
+                    (glow::FRAGMENT_SHADER, &[version, fragment].concat()),
+                ],
+            )
+            .map_err(|log| gst::loggable_error!(CAT, "Failed to build program: {log}"))?;
+
+            match FullscreenQuad::new(gl) {
+                Ok(quad) => Ok((program, quad)),
+                Err(err) => {
+                    unsafe { gl.delete_program(program) };
+                    Err(gst::loggable_error!(CAT, "Failed to create quad: {err}"))
+                }
+            }
+        }
+
+        pub(super) fn glow(&self) -> Option<GlowContext> {
+            self.state
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|state| state.glow.clone())
+        }
+
+        pub(super) fn capture_gl_errors<R>(
+            &self,
+            func: impl FnOnce() -> R,
+        ) -> Result<R, gst::LoggableError> {
+            let Some(glow) = self.glow() else {
+                return Err(gst::loggable_error!(CAT, "No glow context before gl_start"));
+            };
+            self.capture_gl_errors_with(&glow, func)
+        }
+
+        /// [`Self::capture_gl_errors`] for callers already holding the state
+        fn capture_gl_errors_with<R>(
+            &self,
+            glow: &GlowContext,
+            func: impl FnOnce() -> R,
+        ) -> Result<R, gst::LoggableError> {
+            // Capturing makes the driver report synchronously, which is only worth it when the
+            // errors fail the frame, the debug callback logs them anyway
+            if !self.settings.lock().unwrap().raise_gl_errors {
+                return Ok(func());
+            }
+            let (result, errors) = glow.capture_errors(func);
+            let Some(first) = errors.first() else {
+                return Ok(result);
+            };
+
+            gst::element_imp_error!(
+                self,
+                gst::LibraryError::Failed,
+                ("GL error: {}", first),
+                ["{}", errors.join("\n")]
+            );
+            Err(gst::loggable_error!(CAT, "GL errors while rendering"))
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for GlowFilter {
+        const NAME: &'static str = "GstDekaGlowFilter";
+        const ABSTRACT: bool = true;
+        type Type = super::GlowFilter;
+        type ParentType = gst_gl::GLFilter;
+        type Class = super::GlowFilterClass;
+    }
+
+    impl ObjectImpl for GlowFilter {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                vec![glib::ParamSpecBoolean::builder("raise-gl-errors")
+                    .nick("Raise GL errors")
+                    .blurb("Fail with an element error on GL errors reported through KHR_debug")
+                    .default_value(DEFAULT_RAISE_GL_ERRORS)
+                    .mutable_playing()
+                    .build()]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let mut settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "raise-gl-errors" => {
+                    let raise_gl_errors = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing raise-gl-errors from {} to {}",
+                        settings.raise_gl_errors,
+                        raise_gl_errors
+                    );
+                    settings.raise_gl_errors = raise_gl_errors;
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "raise-gl-errors" => settings.raise_gl_errors.to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+
+    impl GstObjectImpl for GlowFilter {}
+
+    impl ElementImpl for GlowFilter {}
+
+    impl BaseTransformImpl for GlowFilter {
+        const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+        const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+        const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+        fn query(&self, direction: gst::PadDirection, query: &mut gst::QueryRef) -> bool {
+            if self.obj().handle_glow_context_query(query) {
+                return true;
+            }
+            BaseTransformImplExt::parent_query(self, direction, query)
+        }
+    }
+
+    impl GLBaseFilterImpl for GlowFilter {
+        fn gl_start(&self) -> Result<(), gst::LoggableError> {
+            let obj = self.obj();
+            let gl_base_filter = obj.upcast_ref::<gst_gl::GLBaseFilter>();
+
+            if !gl_base_filter.find_glow_context() {
+                return Err(gst::loggable_error!(CAT, "Cannot find glow context"));
+            }
+            let Some(glow) = gl_base_filter.glow_context() else {
+                return Err(gst::loggable_error!(CAT, "Cannot find glow context"));
+            };
+
+            let program = match obj.class().as_ref().fragment_shader {
+                Some(fragment) => Some(Self::build_program(&glow, fragment)?),
+                None => None,
+            };
+            *self.state.lock().unwrap() = Some(State { glow, program });
+
+            self.parent_gl_start()
+        }
+
+        fn gl_stop(&self) {
+            if let Some(State {
+                glow,
+                program: Some((program, quad)),
+            }) = self.state.lock().unwrap().take()
+            {
+                glow.with_glow(|gl| {
+                    quad.delete(gl);
+                    unsafe { gl.delete_program(program) };
+                });
+            }
+            self.parent_gl_stop()
+        }
+    }
+
+    impl GLFilterImpl for GlowFilter {
+        const MODE: GLFilterMode = GLFilterMode::Texture;
+
+        fn filter_texture(
+            &self,
+            input: &gst_gl::GLMemory,
+            output: &gst_gl::GLMemory,
+        ) -> Result<(), gst::LoggableError> {
+            let state_lock = self.state.lock().unwrap();
+            let Some(State {
+                glow,
+                program: Some((program, quad)),
+            }) = &*state_lock
+            else {
+                return Err(gst::loggable_error!(CAT, "Program is not built"));
+            };
+            let (Some(input_texture), Some(output_texture)) =
+                (input.as_glow_texture(), output.as_glow_texture())
+            else {
+                return Err(gst::loggable_error!(CAT, "Memory has no texture"));
+            };
+
+            let obj = self.obj();
+            let Some(filter_glow) = obj.class().as_ref().filter_glow else {
+                return Err(gst::loggable_error!(CAT, "Subclass has no filter"));
+            };
+
+            let gl = glow.glow();
+            let mut result = Ok(());
+            let rendered = self.capture_gl_errors_with(glow, || {
+                obj.render_to_target(input, output, |_, _| {
+                    unsafe {
+                        gl.use_program(Some(*program));
+                        gl.active_texture(glow::TEXTURE0);
+                        gl.bind_texture(glow::TEXTURE_2D, Some(input_texture));
+                        gl.uniform_1_i32(gl.get_uniform_location(*program, "tex").as_ref(), 0);
+                    }
+
+                    result = filter_glow(&obj, gl, *program, input_texture, output_texture);
+                    if result.is_ok() {
+                        quad.draw(
+                            gl,
+                            super::POSITION_LOCATION as i32,
+                            super::TEXCOORD_LOCATION as i32,
+                        );
+                    }
+
+                    unsafe { gl.use_program(None) };
+                    result.is_ok()
+                })
+            })?;
+
+            // The subclass error says more than the failed rendering
+            result?;
+            rendered?;
+
+            Ok(())
+        }
+    }
+}
@@ -1,15 +1,67 @@
-#version 300 es
-
-#extension GL_OES_EGL_image_external : require
+// Version, SAMPLER, INPUT_*, OUTPUT_COMPONENTS and OUTPUT_GRAY matching the caps and properties
+// are prepended at runtime
 
 precision highp float;
 
 in vec2 v_texcoord;
 out vec4 outColor;
 
-uniform samplerExternalOES tex;
+// RGB, or luma of the YUV formats
+uniform SAMPLER tex;
+#if defined(INPUT_NV12)
+uniform SAMPLER tex_uv;
+#elif defined(INPUT_I420)
+uniform SAMPLER tex_u;
+uniform SAMPLER tex_v;
+#endif
 uniform float width;
 uniform float height;
+// Size of the chroma planes in pixels
+uniform vec2 chroma_size;
+// Rows of the YUV to RGB matrix, applied after the offset is subtracted
+uniform vec3 yuv_to_rgb[3];
+uniform vec3 yuv_offset;
+// Row-major 3x3 kernel, the first row is the top one
+uniform float kernel[9];
+#ifdef OUTPUT_COMPONENTS
+// Kernel rotated clockwise, giving Gx when the kernel gives Gy
+uniform float kernel_x[9];
+#endif
+uniform float scale;
+uniform float bias;
+
+vec4 sample_plane(SAMPLER plane, vec2 coord, vec2 size)
+{
+#ifdef TEXTURE_RECTANGLE
+    // Rectangle textures are addressed in pixels
+    return texture(plane, coord * size);
+#else
+    return texture(plane, coord);
+#endif
+}
+
+vec3 sample_tex(vec2 coord)
+{
+#if defined(INPUT_NV12) || defined(INPUT_I420)
+    float y = sample_plane(tex, coord, vec2(width, height)).r;
+#ifdef YUV_TO_RGB
+#ifdef INPUT_NV12
+    vec2 uv = sample_plane(tex_uv, coord, chroma_size).rg;
+#else
+    vec2 uv = vec2(
+        sample_plane(tex_u, coord, chroma_size).r,
+        sample_plane(tex_v, coord, chroma_size).r
+    );
+#endif
+    vec3 yuv = vec3(y, uv) - yuv_offset;
+    return vec3(dot(yuv_to_rgb[0], yuv), dot(yuv_to_rgb[1], yuv), dot(yuv_to_rgb[2], yuv));
+#else
+    return vec3(y);
+#endif
+#else
+    return sample_plane(tex, coord, vec2(width, height)).rgb;
+#endif
+}
 
 // Sample from https://learnopengl.com/Advanced-OpenGL/Framebuffers
 void main()
@@ -29,18 +81,34 @@ void main()
         vec2( offset_x, -offset_y)  // bottom-right    
     );
 
-    float kernel[9] = float[](
-        1.0, 2.0, 1.0,
-        0.0, 0.0, 0.0,
-        -1.0, -2.0, -1.0
-    );
-
     vec3 col = vec3(0.0);
+#ifdef OUTPUT_COMPONENTS
+    vec3 col_x = vec3(0.0);
+#endif
     for(int i = 0; i < 9; i++)
     {
-        vec3 c = texture2D(tex, v_texcoord.xy + offsets[i]).rgb;
+        vec3 c = sample_tex(v_texcoord.xy + offsets[i]);
         col += c * kernel[i];
+#ifdef OUTPUT_COMPONENTS
+        col_x += c * kernel_x[i];
+#endif
     }
 
-    outColor = vec4(col, 1.0);
+    // Filtering is linear, so this is the filtered BT.601 luma of RGB input
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+#if defined(OUTPUT_COMPONENTS)
+    // Signed gradients of the luma, -1..1 is stored as 0..1 with 0.5 for no gradient
+    vec2 gradient = vec2(dot(col_x, luma), dot(col, luma)) * scale * 0.5 + 0.5;
+#ifdef OUTPUT_GRAY
+    // Like on the CPU, single channel formats get Gx only
+    outColor = vec4(vec3(gradient.x), 1.0);
+#else
+    outColor = vec4(gradient, 0.0, 1.0);
+#endif
+#elif defined(OUTPUT_GRAY)
+    float gray = dot(col, luma);
+    outColor = vec4(vec3(gray * scale + bias), 1.0);
+#else
+    outColor = vec4(col * scale + bias, 1.0);
+#endif
 }
\ No newline at end of file
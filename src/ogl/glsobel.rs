@@ -1,8 +1,18 @@
 use gst::glib;
 use gst::prelude::*;
 
+use crate::ogl::glow_filter::GlowFilter;
+
 glib::wrapper! {
-    pub struct GlSobel(ObjectSubclass<imp::GlSobel>) @extends gst_gl::GLFilter, gst_gl::GLBaseFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+    /// GL Sobel filter
+    ///
+    /// Float output (RG16F, RGBA16F, RGBA32F) cannot be negotiated: `GstGLFormat` has no float
+    /// texture formats, so `GLMemory` can neither allocate nor wrap them, and there are no float
+    /// video formats either. Signed gradients are stored with the explicit encoding of
+    /// `output-mode=components` instead: Gx in red and Gy in green with half range offset,
+    /// normalized so the largest possible response is not clamped, 16 bit formats keep the
+    /// precision. Magnitudes of `output-mode=kernel` above 1 are clamped.
+    pub struct GlSobel(ObjectSubclass<imp::GlSobel>) @extends GlowFilter, gst_gl::GLFilter, gst_gl::GLBaseFilter, gst_base::BaseTransform, gst::Element, gst::Object;
 }
 
 pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
@@ -17,6 +27,8 @@ pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
 mod imp {
     use std::sync::{LazyLock, Mutex};
 
+    use glow::HasContext;
+
     use gst::{
         glib::subclass::{object::ObjectImpl, types::ObjectSubclass},
         prelude::*,
@@ -24,16 +36,22 @@ mod imp {
     };
     use gst_base::subclass::{prelude::BaseTransformImpl, BaseTransformMode};
     use gst_gl::{
-        prelude::{GLBaseFilterExt, GLFilterExt},
+        prelude::{GLBaseFilterExt, GLFilterExt, GLVideoFrameExt},
         subclass::{
-            prelude::{GLBaseFilterImpl, GLFilterImpl},
+            prelude::{GLBaseFilterImpl, GLBaseFilterImplExt, GLFilterImpl, GLFilterImplExt},
             GLFilterMode,
         },
-        GLSLStage, GLShader,
+        GLFormat, GLSLStage, GLShader, GLTextureTarget,
     };
-    use gst_video::VideoFormat;
+    use gst_video::{VideoColorMatrix, VideoColorRange, VideoFormat};
 
+    use crate::cpu_common::kernel::{self, Kernel};
     use crate::glib;
+    use crate::glib::translate::{from_glib_borrow, ToGlibPtr};
+    use crate::glow_gst_inteop::prelude::*;
+    use crate::glow_gst_inteop::GlowContext;
+    use crate::ogl::glow_filter::{GlowFilterExt, GlowFilterImpl};
+    use crate::ogl::quad::FullscreenQuad;
 
     static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
         gst::DebugCategory::new(
@@ -43,26 +61,504 @@ mod imp {
         )
     });
 
+    /// Sobel-Y, positive when the intensity grows upwards
+    const DEFAULT_KERNEL: [f32; 9] = [
+        1.0, 2.0, 1.0, //
+        0.0, 0.0, 0.0, //
+        -1.0, -2.0, -1.0, //
+    ];
+    const DEFAULT_SCALE: f64 = 1.0;
+    const DEFAULT_BIAS: f64 = 0.0;
+    const DEFAULT_YUV_MODE: YuvMode = YuvMode::Luma;
+    const DEFAULT_OUTPUT_MODE: OutputMode = OutputMode::Kernel;
+
+    #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+    #[repr(u32)]
+    #[enum_type(name = "GstDekaGlSobelYuvMode")]
+    pub enum YuvMode {
+        #[enum_value(name = "Luma: filter the Y plane only", nick = "luma")]
+        Luma = 0,
+        #[enum_value(
+            name = "RGB: filter RGB converted from YUV with the caps colorimetry",
+            nick = "rgb"
+        )]
+        Rgb = 1,
+    }
+
+    #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+    #[repr(u32)]
+    #[enum_type(name = "GstDekaGlSobelOutputMode")]
+    pub enum OutputMode {
+        #[enum_value(
+            name = "Kernel: weighted sum of each channel with scale and bias",
+            nick = "kernel"
+        )]
+        Kernel = 0,
+        #[enum_value(
+            name = "Components: Gx in red and Gy in green with half range offset",
+            nick = "components"
+        )]
+        Components = 1,
+    }
+
+    #[derive(Debug, Clone)]
+    struct Settings {
+        kernel: Kernel,
+        scale: f64,
+        bias: f64,
+        yuv_mode: YuvMode,
+        output_mode: OutputMode,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                kernel: Kernel::new(3, 3, DEFAULT_KERNEL.to_vec()).expect("valid kernel"),
+                scale: DEFAULT_SCALE,
+                bias: DEFAULT_BIAS,
+                yuv_mode: DEFAULT_YUV_MODE,
+                output_mode: DEFAULT_OUTPUT_MODE,
+            }
+        }
+    }
+
+    /// Texture targets accepted on the sink pad
+    const SINK_TEXTURE_TARGETS: [&str; 3] = ["2D", "rectangle", "external-oes"];
+
+    const SINK_FORMATS: [VideoFormat; 5] = [
+        VideoFormat::Rgba,
+        VideoFormat::Rgbx,
+        VideoFormat::Rgb,
+        VideoFormat::Nv12,
+        VideoFormat::I420,
+    ];
+
+    /// Output formats with the texture format they are rendered to
+    ///
+    /// Only normalized formats, GL memory has no float ones, see [`super::GlSobel`]. The 16 bit
+    /// ones are the most precise. Negative values need a bias, or the signed encoding of
+    /// [`OutputMode::Components`], to survive any of them.
+    const SRC_FORMATS: [(VideoFormat, GLFormat); 4] = [
+        (VideoFormat::Rgba, GLFormat::Rgba8),
+        (VideoFormat::Gray8, GLFormat::R8),
+        (VideoFormat::Gray16Le, GLFormat::R16),
+        (VideoFormat::Rgba64Le, GLFormat::Rgba16),
+    ];
+
+    /// How the input frame is split into textures
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Planes {
+        Rgb,
+        Nv12,
+        I420,
+    }
+
+    impl Planes {
+        fn new(format: VideoFormat) -> Self {
+            match format {
+                VideoFormat::Nv12 => Planes::Nv12,
+                VideoFormat::I420 => Planes::I420,
+                _ => Planes::Rgb,
+            }
+        }
+
+        /// Sampler uniforms of the planes in order
+        fn samplers(self) -> &'static [&'static str] {
+            match self {
+                Planes::Rgb => &["tex"],
+                Planes::Nv12 => &["tex", "tex_uv"],
+                Planes::I420 => &["tex", "tex_u", "tex_v"],
+            }
+        }
+
+        fn define(self) -> &'static str {
+            match self {
+                Planes::Rgb => "#define INPUT_RGB\n",
+                Planes::Nv12 => "#define INPUT_NV12\n",
+                Planes::I420 => "#define INPUT_I420\n",
+            }
+        }
+    }
+
+    /// Input properties the shader source depends on
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct ShaderVariant {
+        target: GLTextureTarget,
+        planes: Planes,
+        /// YUV is converted to RGB before filtering
+        yuv_to_rgb: bool,
+        /// Output has a single channel
+        gray: bool,
+        /// Output is [`OutputMode::Components`]
+        components: bool,
+    }
+
+    /// YUV to RGB conversion of the negotiated colorimetry
+    #[derive(Debug, Clone, Copy)]
+    struct YuvConversion {
+        /// Rows of the matrix including range expansion
+        matrix: [[f32; 3]; 3],
+        offset: [f32; 3],
+    }
+
+    impl YuvConversion {
+        /// BT.709 for the caps saying so, BT.601 otherwise
+        fn new(info: &gst_video::VideoInfo) -> Self {
+            let colorimetry = info.colorimetry();
+            let (kr, kb) = match colorimetry.matrix() {
+                VideoColorMatrix::Bt709 => (0.2126, 0.0722),
+                _ => (0.299, 0.114),
+            };
+            let kg = 1.0 - kr - kb;
+
+            let (y_scale, c_scale, y_offset) = match colorimetry.range() {
+                VideoColorRange::Range0_255 => (1.0, 1.0, 0.0),
+                _ => (255.0 / 219.0, 255.0 / 224.0, 16.0 / 255.0),
+            };
+
+            Self {
+                matrix: [
+                    [y_scale, 0.0, c_scale * 2.0 * (1.0 - kr)],
+                    [
+                        y_scale,
+                        -c_scale * 2.0 * kb * (1.0 - kb) / kg,
+                        -c_scale * 2.0 * kr * (1.0 - kr) / kg,
+                    ],
+                    [y_scale, c_scale * 2.0 * (1.0 - kb), 0.0],
+                ],
+                offset: [y_offset, 128.0 / 255.0, 128.0 / 255.0],
+            }
+        }
+    }
+
+    /// GL resources living between `gl_start` and `gl_stop`
+    struct State {
+        /// Used for binding the textures of all input planes
+        glow: GlowContext,
+        quad: FullscreenQuad,
+    }
+
+    /// Output frame mapped for rendering through GL
+    ///
+    /// `filter` gets the output as `&gst::Buffer`, so it is mapped through the buffer pointer like
+    /// `GLFilter` maps it for texture filters, without a `&mut gst::BufferRef`. Mapping fails
+    /// instead when someone else holds a reference to the buffer. Unmapping marks the texture as
+    /// changed for later downloads.
+    struct MappedOutput(gst_video::ffi::GstVideoFrame);
+
+    impl MappedOutput {
+        fn map(buffer: &gst::Buffer, info: &gst_video::VideoInfo) -> Result<Self, glib::BoolError> {
+            let mut frame = std::mem::MaybeUninit::zeroed();
+            let mapped = unsafe {
+                gst_video::ffi::gst_video_frame_map(
+                    frame.as_mut_ptr(),
+                    info.to_glib_none().0,
+                    buffer.as_mut_ptr(),
+                    gst::ffi::GST_MAP_WRITE | gst_gl::ffi::GST_MAP_GL as gst::ffi::GstMapFlags,
+                )
+            };
+            if mapped == glib::ffi::GFALSE {
+                return Err(glib::bool_error!("Failed to map output buffer for writing"));
+            }
+
+            Ok(Self(unsafe { frame.assume_init() }))
+        }
+
+        /// Memory of the first plane, the only one of the output formats
+        fn memory(&self) -> Result<glib::translate::Borrowed<gst_gl::GLMemory>, glib::BoolError> {
+            let memory = self.0.map[0].memory;
+            let is_gl = unsafe { gst_gl::ffi::gst_is_gl_memory(memory) } != glib::ffi::GFALSE;
+            if !is_gl {
+                return Err(glib::bool_error!("Output memory is not GL memory"));
+            }
+
+            Ok(unsafe { from_glib_borrow(memory as *mut gst_gl::ffi::GstGLMemory) })
+        }
+    }
+
+    impl Drop for MappedOutput {
+        fn drop(&mut self) {
+            unsafe { gst_video::ffi::gst_video_frame_unmap(&mut self.0) };
+        }
+    }
+
     pub struct GlSobel {
-        shader: Mutex<Option<gst_gl::GLShader>>,
+        settings: Mutex<Settings>,
+        /// Shader with the input properties it was compiled for
+        shader: Mutex<Option<(ShaderVariant, GLShader)>>,
+        /// Set when caps are negotiated
+        input_info: Mutex<Option<gst_video::VideoInfo>>,
+        input_target: Mutex<GLTextureTarget>,
+        output_info: Mutex<Option<gst_video::VideoInfo>>,
+        state: Mutex<Option<State>>,
     }
 
-    impl GlSobel {}
+    impl GlSobel {
+        /// Shader variant for the negotiated input and current settings
+        fn shader_variant(&self) -> ShaderVariant {
+            let format = self
+                .input_info
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map_or(VideoFormat::Rgba, |info| info.format());
+            let planes = Planes::new(format);
+
+            let output_format = self.output_format();
+            let settings = self.settings.lock().unwrap();
+
+            ShaderVariant {
+                target: *self.input_target.lock().unwrap(),
+                planes,
+                yuv_to_rgb: planes != Planes::Rgb && settings.yuv_mode == YuvMode::Rgb,
+                gray: matches!(output_format, VideoFormat::Gray8 | VideoFormat::Gray16Le),
+                components: settings.output_mode == OutputMode::Components,
+            }
+        }
+
+        fn output_format(&self) -> VideoFormat {
+            self.output_info
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map_or(VideoFormat::Rgba, |info| info.format())
+        }
+
+        /// Compiles the shader if it was not compiled for `variant` yet, must be called on the GL
+        /// thread
+        fn update_shader(&self, variant: ShaderVariant) -> Result<(), gst::LoggableError> {
+            let mut shader = self.shader.lock().unwrap();
+            if shader
+                .as_ref()
+                .is_some_and(|(compiled, _)| *compiled == variant)
+            {
+                return Ok(());
+            }
+
+            gst::debug!(CAT, imp = self, "Compiling shader for {:?}", variant);
+            *shader = Some((variant, self.compile_shader(variant)?));
+
+            Ok(())
+        }
+
+        /// Output formats the GL context can render to, all of them before the context is known
+        fn supported_src_formats(&self) -> Vec<VideoFormat> {
+            let obj = self.obj();
+            let context = GLBaseFilterExt::context(obj.upcast_ref::<gst_gl::GLBaseFilter>());
+
+            SRC_FORMATS
+                .iter()
+                .filter(|(_, gl_format)| {
+                    context
+                        .as_ref()
+                        .is_none_or(|context| GLFormat::is_supported(context, *gl_format))
+                })
+                .map(|(format, _)| *format)
+                .collect()
+        }
+
+        fn compile_shader(&self, variant: ShaderVariant) -> Result<GLShader, gst::LoggableError> {
+            let obj = self.obj();
+            let gl_base_filter = obj.upcast_ref::<gst_gl::GLBaseFilter>();
+
+            let Some(ctx) = GLBaseFilterExt::context(gl_base_filter) else {
+                return Err(gst::loggable_error!(CAT, "Cannot find GL context"));
+            };
+
+            // Rectangle textures are only supported by desktop GLSL, which can't be linked with ES
+            let (version, sampler) = match variant.target {
+                GLTextureTarget::Rectangle => (
+                    "#version 150\n",
+                    "#define SAMPLER sampler2DRect\n#define TEXTURE_RECTANGLE\n",
+                ),
+                GLTextureTarget::ExternalOes => (
+                    "#version 300 es\n",
+                    "#extension GL_OES_EGL_image_external_essl3 : require\n\
+                     #define SAMPLER samplerExternalOES\n",
+                ),
+                _ => ("#version 300 es\n", "#define SAMPLER sampler2D\n"),
+            };
+            let conversion = if variant.yuv_to_rgb {
+                "#define YUV_TO_RGB\n"
+            } else {
+                ""
+            };
+            let output = match (variant.components, variant.gray) {
+                (true, true) => "#define OUTPUT_COMPONENTS\n#define OUTPUT_GRAY\n",
+                (true, false) => "#define OUTPUT_COMPONENTS\n",
+                (false, true) => "#define OUTPUT_GRAY\n",
+                (false, false) => "",
+            };
+
+            let vert_stage = GLSLStage::new(&ctx, glow::VERTEX_SHADER);
+            vert_stage.set_strings(
+                gst_gl::GLSLVersion::None,
+                gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
+                &[version, include_str!("base.vert")],
+            )?;
+            if let Err(err) = vert_stage.compile() {
+                return Err(gst::loggable_error!(CAT, "Vert compile error: {err}"));
+            }
+            let frag_stage = GLSLStage::new(&ctx, glow::FRAGMENT_SHADER);
+            frag_stage.set_strings(
+                gst_gl::GLSLVersion::None,
+                gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
+                &[
+                    version,
+                    sampler,
+                    variant.planes.define(),
+                    conversion,
+                    output,
+                    include_str!("glsobel.frag"),
+                ],
+            )?;
+            if let Err(err) = frag_stage.compile() {
+                return Err(gst::loggable_error!(CAT, "Frag compile error: {err}"));
+            }
+
+            let shader = GLShader::new(&ctx);
+            shader.attach(&vert_stage)?;
+            shader.attach(&frag_stage)?;
+            if let Err(err) = shader.link() {
+                return Err(gst::loggable_error!(CAT, "Link error: {err}"));
+            }
+
+            Ok(shader)
+        }
+    }
 
     #[glib::object_subclass]
     impl ObjectSubclass for GlSobel {
         const NAME: &'static str = "GstDekaGlSobel";
         type Type = super::GlSobel;
-        type ParentType = gst_gl::GLFilter;
+        type ParentType = super::GlowFilter;
 
         fn with_class(_klass: &Self::Class) -> Self {
             Self {
+                settings: Mutex::new(Settings::default()),
                 shader: Mutex::new(None),
+                input_info: Mutex::new(None),
+                input_target: Mutex::new(GLTextureTarget::_2d),
+                output_info: Mutex::new(None),
+                state: Mutex::new(None),
+            }
+        }
+    }
+
+    impl ObjectImpl for GlSobel {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                vec![
+                    kernel::param_spec("Array of 3 kernel rows of 3 coefficients"),
+                    glib::ParamSpecDouble::builder("scale")
+                        .nick("Scale")
+                        .blurb("Value the weighted sum is multiplied by")
+                        .default_value(DEFAULT_SCALE)
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecDouble::builder("bias")
+                        .nick("Bias")
+                        .blurb("Value added to the result after scaling, 1.0 is the full range")
+                        .default_value(DEFAULT_BIAS)
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecEnum::builder_with_default("yuv-mode", DEFAULT_YUV_MODE)
+                        .nick("YUV mode")
+                        .blurb("What is filtered for NV12 and I420 input")
+                        .mutable_playing()
+                        .build(),
+                    glib::ParamSpecEnum::builder_with_default("output-mode", DEFAULT_OUTPUT_MODE)
+                        .nick("Output mode")
+                        .blurb(
+                            "How the result is stored, components keep the sign of the \
+                             gradients without float formats",
+                        )
+                        .mutable_playing()
+                        .build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let mut settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "kernel" => match Kernel::from_property(value, Some(3)) {
+                    Ok(kernel) => {
+                        gst::info!(
+                            CAT,
+                            imp = self,
+                            "Changing kernel from {:?} to {:?}",
+                            settings.kernel,
+                            kernel
+                        );
+                        settings.kernel = kernel;
+                    }
+                    Err(err) => {
+                        gst::error!(CAT, imp = self, "Invalid kernel {:?}: {}", value, err);
+                    }
+                },
+                "scale" => {
+                    let scale = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing scale from {} to {}",
+                        settings.scale,
+                        scale
+                    );
+                    settings.scale = scale;
+                }
+                "bias" => {
+                    let bias = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing bias from {} to {}",
+                        settings.bias,
+                        bias
+                    );
+                    settings.bias = bias;
+                }
+                "yuv-mode" => {
+                    let yuv_mode = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing YUV mode from {:?} to {:?}",
+                        settings.yuv_mode,
+                        yuv_mode
+                    );
+                    settings.yuv_mode = yuv_mode;
+                }
+                "output-mode" => {
+                    let output_mode = value.get().expect("type checked upstream");
+                    gst::info!(
+                        CAT,
+                        imp = self,
+                        "Changing output mode from {:?} to {:?}",
+                        settings.output_mode,
+                        output_mode
+                    );
+                    settings.output_mode = output_mode;
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let settings = self.settings.lock().unwrap();
+            match pspec.name() {
+                "kernel" => settings.kernel.to_array().to_value(),
+                "scale" => settings.scale.to_value(),
+                "bias" => settings.bias.to_value(),
+                "yuv-mode" => settings.yuv_mode.to_value(),
+                "output-mode" => settings.output_mode.to_value(),
+                _ => unimplemented!(),
             }
         }
     }
 
-    impl ObjectImpl for GlSobel {}
     impl GstObjectImpl for GlSobel {}
     impl ElementImpl for GlSobel {
         fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -81,19 +577,14 @@ mod imp {
         fn pad_templates() -> &'static [gst::PadTemplate] {
             static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
                 let src_caps = gst_video::VideoCapsBuilder::new()
-                    .format_list([VideoFormat::Rgba])
+                    .format_list(SRC_FORMATS.iter().map(|(format, _)| *format))
                     .field("texture-target", "2D")
                     .features([gst_gl::CAPS_FEATURE_MEMORY_GL_MEMORY])
                     .build();
 
                 let sink_caps = gst_video::VideoCapsBuilder::new()
-                    .format_list([
-                        VideoFormat::Rgba,
-                        VideoFormat::Rgbx,
-                        VideoFormat::Rgb,
-                        VideoFormat::Nv12,
-                    ])
-                    .field("texture-target", "external-oes")
+                    .format_list(SINK_FORMATS)
+                    .field("texture-target", gst::List::new(SINK_TEXTURE_TARGETS))
                     .features([gst_gl::CAPS_FEATURE_MEMORY_GL_MEMORY])
                     .build();
 
@@ -134,16 +625,26 @@ mod imp {
                 let mut caps = caps.clone();
 
                 for s in caps.make_mut().iter_mut() {
-                    s.set("texture-target", "external-oes");
+                    s.set("texture-target", gst::List::new(SINK_TEXTURE_TARGETS));
+                    s.set(
+                        "format",
+                        gst::List::new(SINK_FORMATS.iter().map(|format| format.to_str())),
+                    );
+                    s.remove_fields(["colorimetry", "chroma-site"]);
                 }
 
                 caps
             } else {
                 let mut caps = caps.clone();
 
+                let formats = self.supported_src_formats();
                 for s in caps.make_mut().iter_mut() {
                     s.set("texture-target", "2D");
-                    s.set("format", VideoFormat::Rgba.to_str());
+                    s.set(
+                        "format",
+                        gst::List::new(formats.iter().map(|format| format.to_str())),
+                    );
+                    s.remove_fields(["colorimetry", "chroma-site"]);
                 }
 
                 caps
@@ -170,64 +671,199 @@ mod imp {
 
     impl GLBaseFilterImpl for GlSobel {
         fn gl_start(&self) -> Result<(), gst::LoggableError> {
-            let obj = self.obj();
-            let gl_base_filter = obj.upcast_ref::<gst_gl::GLBaseFilter>();
-
-            let Some(ctx) = GLBaseFilterExt::context(gl_base_filter) else {
-                return Err(gst::loggable_error!(CAT, "Cannot find GL context"));
-            };
+            // The base class finds the glow context
+            self.parent_gl_start()?;
+            let glow = self.obj().glow().expect("found by the base class");
+
+            let quad = FullscreenQuad::new(glow.glow()).map_err(|err| {
+                self.parent_gl_stop();
+                gst::loggable_error!(CAT, "Failed to create quad: {err}")
+            })?;
+            *self.state.lock().unwrap() = Some(State { glow, quad });
+
+            self.update_shader(self.shader_variant())
+                .inspect_err(|_| self.gl_stop())
+        }
 
-            let vert_stage = GLSLStage::new(&ctx, glow::VERTEX_SHADER);
-            vert_stage.set_strings(
-                gst_gl::GLSLVersion::None,
-                gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
-                &[&include_str!("base.vert")],
-            )?;
-            if let Err(err) = vert_stage.compile() {
-                return Err(gst::loggable_error!(CAT, "Vert compile error: {err}"));
-            }
-            let frag_stage = GLSLStage::new(&ctx, glow::FRAGMENT_SHADER);
-            frag_stage.set_strings(
-                gst_gl::GLSLVersion::None,
-                gst_gl::GLSLProfile::ES | gst_gl::GLSLProfile::COMPATIBILITY,
-                &[&include_str!("glsobel.frag")],
-            )?;
-            if let Err(err) = frag_stage.compile() {
-                return Err(gst::loggable_error!(CAT, "Frag compile error: {err}"));
+        fn gl_stop(&self) {
+            *self.shader.lock().unwrap() = None;
+            if let Some(State { glow, quad }) = self.state.lock().unwrap().take() {
+                quad.delete(glow.glow());
             }
+            self.parent_gl_stop()
+        }
 
-            let shader = GLShader::new(&ctx);
-            shader.attach(&vert_stage)?;
-            shader.attach(&frag_stage)?;
-            if let Err(err) = shader.link() {
-                return Err(gst::loggable_error!(CAT, "Link error: {err}"));
+        fn gl_set_caps(
+            &self,
+            incaps: &gst::Caps,
+            outcaps: &gst::Caps,
+        ) -> Result<(), gst::LoggableError> {
+            let output_format = self.output_format();
+            if !self.supported_src_formats().contains(&output_format) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "Output format {} is not supported by the GL context",
+                    output_format.to_str()
+                ));
             }
 
-            *self.shader.lock().unwrap() = Some(shader);
+            // Input target and format may change on renegotiation, the shader must follow them
+            self.update_shader(self.shader_variant())?;
 
-            Ok(())
+            self.parent_gl_set_caps(incaps, outcaps)
         }
     }
     impl GLFilterImpl for GlSobel {
         const ADD_RGBA_PAD_TEMPLATES: bool = false;
-        const MODE: GLFilterMode = GLFilterMode::Texture;
+        const MODE: GLFilterMode = GLFilterMode::Buffer;
 
-        fn filter_texture(
+        fn set_caps(
             &self,
-            input: &gst_gl::GLMemory,
-            output: &gst_gl::GLMemory,
+            incaps: &gst::Caps,
+            outcaps: &gst::Caps,
         ) -> Result<(), gst::LoggableError> {
-            let shader_lock = self.shader.lock().unwrap();
+            let info = gst_video::VideoInfo::from_caps(incaps)
+                .map_err(|_| gst::loggable_error!(CAT, "Invalid input caps {incaps}"))?;
+            if info.is_yuv() {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Input colorimetry {}, YUV to RGB {:?}",
+                    info.colorimetry(),
+                    YuvConversion::new(&info)
+                );
+            }
+            *self.input_info.lock().unwrap() = Some(info);
 
-            let Some(shader) = &*shader_lock else {
+            let target = incaps
+                .structure(0)
+                .and_then(|s| s.get::<&str>("texture-target").ok())
+                .map_or(GLTextureTarget::_2d, GLTextureTarget::from_string);
+            gst::debug!(CAT, imp = self, "Input texture target {:?}", target);
+            *self.input_target.lock().unwrap() = target;
+
+            let out_info = gst_video::VideoInfo::from_caps(outcaps)
+                .map_err(|_| gst::loggable_error!(CAT, "Invalid output caps {outcaps}"))?;
+            gst::debug!(CAT, imp = self, "Output format {}", out_info.format());
+            *self.output_info.lock().unwrap() = Some(out_info);
+
+            GLFilterImplExt::parent_set_caps(self, incaps, outcaps)
+        }
+
+        fn filter(
+            &self,
+            input: &gst::Buffer,
+            output: &gst::Buffer,
+        ) -> Result<(), gst::LoggableError> {
+            // Property changes may require another variant
+            let variant = self.shader_variant();
+            self.update_shader(variant)?;
+
+            let Some(info) = self.input_info.lock().unwrap().clone() else {
+                return Err(gst::loggable_error!(CAT, "Caps are not negotiated"));
+            };
+            let Some(out_info) = self.output_info.lock().unwrap().clone() else {
+                return Err(gst::loggable_error!(CAT, "Caps are not negotiated"));
+            };
+
+            // Mapping with the GL flag uploads the input planes and marks the output texture as
+            // changed, like `GLFilter` does for texture filters
+            let in_frame = gst_gl::GLVideoFrameRef::from_buffer_ref_readable(input, &info)
+                .map_err(|err| gst::loggable_error!(CAT, "Failed to map input: {err}"))?;
+            let out_frame = MappedOutput::map(output, &out_info)
+                .map_err(|err| gst::loggable_error!(CAT, "{err}"))?;
+
+            let samplers = variant.planes.samplers();
+            let planes = (0..samplers.len() as u32)
+                .map(|plane| in_frame.memory(plane))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| gst::loggable_error!(CAT, "Input is not GL memory: {err}"))?;
+            let out_memory = out_frame
+                .memory()
+                .map_err(|err| gst::loggable_error!(CAT, "{err}"))?;
+            // `render_to_target` wants the memory objects the frame keeps alive
+            let first_plane =
+                unsafe { from_glib_borrow::<_, gst_gl::GLMemory>(planes[0].as_ptr()) };
+
+            let settings = self.settings.lock().unwrap().clone();
+            let conversion = YuvConversion::new(&info);
+            // Components are normalized like on the CPU, the largest response becomes 1
+            let (kernel_x, scale) = if variant.components {
+                let norm: f32 = settings
+                    .kernel
+                    .coefficients()
+                    .iter()
+                    .filter(|coefficient| **coefficient > 0.0)
+                    .sum();
+                (
+                    Some(settings.kernel.rotate_clockwise()),
+                    settings.scale as f32 / norm.max(f32::EPSILON),
+                )
+            } else {
+                (None, settings.scale as f32)
+            };
+            let shader_lock = self.shader.lock().unwrap();
+            let Some((_, shader)) = &*shader_lock else {
                 return Err(gst::loggable_error!(CAT, "Shader is not loaded"));
             };
+            let state_lock = self.state.lock().unwrap();
+            let Some(state) = &*state_lock else {
+                return Err(gst::loggable_error!(CAT, "Glow context is not available"));
+            };
 
             let obj = self.obj();
-
-            obj.render_to_target_with_shader(input, output, shader);
-
-            Ok(())
+            let rendered = obj.capture_gl_errors(|| {
+                obj.render_to_target(&first_plane, &out_memory, |_, _| {
+                    // Uniforms are set on the active program
+                    shader.use_();
+                    shader.set_uniform_1f("width", info.width() as f32);
+                    shader.set_uniform_1f("height", info.height() as f32);
+                    shader.set_uniform_1fv("kernel", settings.kernel.coefficients());
+                    shader.set_uniform_1f("scale", scale);
+                    shader.set_uniform_1f("bias", settings.bias as f32);
+                    if let Some(kernel_x) = &kernel_x {
+                        shader.set_uniform_1fv("kernel_x", kernel_x.coefficients());
+                    }
+
+                    if variant.planes != Planes::Rgb {
+                        shader.set_uniform_2f(
+                            "chroma_size",
+                            info.comp_width(1) as f32,
+                            info.comp_height(1) as f32,
+                        );
+                        for (row, [r, g, b]) in conversion.matrix.iter().enumerate() {
+                            shader.set_uniform_3f(&format!("yuv_to_rgb[{row}]"), *r, *g, *b);
+                        }
+                        let [y, u, v] = conversion.offset;
+                        shader.set_uniform_3f("yuv_offset", y, u, v);
+                    }
+
+                    // Every plane gets its own texture unit
+                    let gl = state.glow.glow();
+                    for (idx, (plane, sampler)) in planes.iter().zip(samplers).enumerate() {
+                        unsafe {
+                            gl.active_texture(glow::TEXTURE0 + idx as u32);
+                            gl.bind_texture(variant.target.to_gl(), plane.as_glow_texture());
+                        }
+                        shader.set_uniform_1i(sampler, idx as i32);
+                    }
+
+                    state.quad.draw(
+                        gl,
+                        shader.attribute_location("a_position"),
+                        shader.attribute_location("a_texcoord"),
+                    );
+
+                    // Leave the default unit active for whoever draws next
+                    unsafe { gl.active_texture(glow::TEXTURE0) };
+
+                    true
+                })
+            })?;
+
+            rendered.map_err(|err| gst::loggable_error!(CAT, "Failed to render: {err}"))
         }
     }
+
+    impl GlowFilterImpl for GlSobel {}
 }
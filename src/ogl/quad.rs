@@ -0,0 +1,89 @@
+//!
+//! Fullscreen quad drawn through glow
+//!
+
+use glow::HasContext;
+
+/// Triangle strip covering the viewport, position followed by texture coordinates
+#[rustfmt::skip]
+const VERTICES: [f32; 20] = [
+    -1.0, -1.0, 0.0, 0.0, 0.0,
+     1.0, -1.0, 0.0, 1.0, 0.0,
+    -1.0,  1.0, 0.0, 0.0, 1.0,
+     1.0,  1.0, 0.0, 1.0, 1.0,
+];
+const STRIDE: i32 = 5 * size_of::<f32>() as i32;
+const TEXCOORD_OFFSET: i32 = 3 * size_of::<f32>() as i32;
+
+/// Fullscreen quad in a vertex array owned by the element
+///
+/// `GLFilter` draws its own quad only for the shader passed to `render_to_target_with_shader`,
+/// programs used inside `render_to_target` draw this one. Must be created and deleted on the GL
+/// thread.
+#[derive(Debug)]
+pub(crate) struct FullscreenQuad {
+    vao: glow::NativeVertexArray,
+    vbo: glow::NativeBuffer,
+}
+
+impl FullscreenQuad {
+    pub(crate) fn new(gl: &glow::Context) -> Result<Self, String> {
+        let bytes = VERTICES
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<u8>>();
+
+        unsafe {
+            let vao = gl.create_vertex_array()?;
+            let vbo = match gl.create_buffer() {
+                Ok(vbo) => vbo,
+                Err(err) => {
+                    gl.delete_vertex_array(vao);
+                    return Err(err);
+                }
+            };
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &bytes, glow::STATIC_DRAW);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+            Ok(Self { vao, vbo })
+        }
+    }
+
+    /// Draws the quad with the active program, negative locations are skipped like GL does
+    pub(crate) fn draw(&self, gl: &glow::Context, position: i32, texcoord: i32) {
+        let attributes = [(position, 3, 0), (texcoord, 2, TEXCOORD_OFFSET)]
+            .into_iter()
+            .filter_map(|(location, size, offset)| {
+                u32::try_from(location)
+                    .ok()
+                    .map(|location| (location, size, offset))
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            gl.bind_vertex_array(Some(self.vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+            for &(location, size, offset) in &attributes {
+                gl.vertex_attrib_pointer_f32(location, size, glow::FLOAT, false, STRIDE, offset);
+                gl.enable_vertex_attrib_array(location);
+            }
+
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+            for &(location, _, _) in &attributes {
+                gl.disable_vertex_attrib_array(location);
+            }
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            gl.bind_vertex_array(None);
+        }
+    }
+
+    pub(crate) fn delete(self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_buffer(self.vbo);
+            gl.delete_vertex_array(self.vao);
+        }
+    }
+}
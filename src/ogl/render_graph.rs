@@ -0,0 +1,244 @@
+//!
+//! Multi-pass rendering of a GL filter frame through pooled intermediate textures
+//!
+
+use glow::HasContext;
+use gst::prelude::*;
+use gst_gl::prelude::*;
+use gst_video::VideoFormat;
+
+use crate::glib;
+use crate::glow_gst_inteop::prelude::*;
+use crate::glow_gst_inteop::GlowContext;
+use crate::ogl::quad::FullscreenQuad;
+
+/// Texture sampled by a pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Input texture of the filter
+    Input,
+    /// Texture rendered by the earlier pass of this name
+    Pass(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pass {
+    pub name: String,
+    pub shader: gst_gl::GLShader,
+    /// Sampler uniforms with the textures bound to them
+    pub inputs: Vec<(String, Source)>,
+    /// Format of the intermediate texture, the last pass renders to the output texture instead
+    pub format: VideoFormat,
+}
+
+impl Pass {
+    pub fn new(name: &str, shader: &gst_gl::GLShader) -> Self {
+        Self {
+            name: name.to_owned(),
+            shader: shader.clone(),
+            inputs: Vec::new(),
+            format: VideoFormat::Rgba,
+        }
+    }
+
+    /// Binds `source` to the `sampler` uniform
+    pub fn input(mut self, sampler: &str, source: Source) -> Self {
+        self.inputs.push((sampler.to_owned(), source));
+        self
+    }
+}
+
+/// Chain of passes where each pass samples the filter input or textures of earlier passes
+#[derive(Debug)]
+pub struct RenderGraph {
+    passes: Vec<Pass>,
+    /// Pools of the intermediate passes for the frame size they were created for
+    pools: Option<((u32, u32), Vec<gst_gl::GLBufferPool>)>,
+    /// Created by the first run, deleted by [`RenderGraph::release`]
+    quad: Option<FullscreenQuad>,
+}
+
+impl RenderGraph {
+    pub fn new(passes: Vec<Pass>) -> Result<Self, glib::BoolError> {
+        if passes.is_empty() {
+            return Err(glib::bool_error!("Render graph has no passes"));
+        }
+
+        for (idx, pass) in passes.iter().enumerate() {
+            for (sampler, source) in &pass.inputs {
+                if let Source::Pass(name) = source {
+                    if !passes[..idx].iter().any(|earlier| earlier.name == *name) {
+                        return Err(glib::bool_error!(
+                            "Sampler {} of pass {} uses {}, which is not an earlier pass",
+                            sampler,
+                            pass.name,
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            passes,
+            pools: None,
+            quad: None,
+        })
+    }
+
+    /// Creates the pools of intermediate textures unless they already have `size`
+    fn update_pools(
+        &mut self,
+        context: &gst_gl::GLContext,
+        size: (u32, u32),
+    ) -> Result<(), glib::BoolError> {
+        if self
+            .pools
+            .as_ref()
+            .is_some_and(|(pool_size, _)| *pool_size == size)
+        {
+            return Ok(());
+        }
+
+        if let Some((_, pools)) = self.pools.take() {
+            for pool in pools {
+                pool.set_active(false)?;
+            }
+        }
+
+        let intermediate = &self.passes[..self.passes.len() - 1];
+        let pools = intermediate
+            .iter()
+            .map(|pass| {
+                let info = gst_video::VideoInfo::builder(pass.format, size.0, size.1).build()?;
+                let caps = gst_video::VideoCapsBuilder::new()
+                    .format(pass.format)
+                    .width(size.0 as i32)
+                    .height(size.1 as i32)
+                    .features([gst_gl::CAPS_FEATURE_MEMORY_GL_MEMORY])
+                    .build();
+
+                // A frame takes one texture, the second one covers a frame still in flight
+                let pool = gst_gl::GLBufferPool::new(context);
+                let mut config = pool.config();
+                config.set_params(Some(&caps), info.size() as u32, 1, 2);
+                pool.set_config(config)?;
+                pool.set_active(true)?;
+
+                Ok(pool)
+            })
+            .collect::<Result<Vec<_>, glib::BoolError>>()?;
+
+        self.pools = Some((size, pools));
+
+        Ok(())
+    }
+
+    /// Runs all passes, the last one renders to `output`, must be called on the GL thread
+    ///
+    /// `set_uniforms` is called with the active program of every pass before drawing
+    pub fn run(
+        &mut self,
+        filter: &gst_gl::GLFilter,
+        glow: &GlowContext,
+        input: &gst_gl::GLMemory,
+        output: &gst_gl::GLMemory,
+        mut set_uniforms: impl FnMut(&Pass),
+    ) -> Result<(), glib::BoolError> {
+        let size = (
+            output.texture_width() as u32,
+            output.texture_height() as u32,
+        );
+        self.update_pools(glow.gst_gl_context(), size)?;
+        let Some((_, pools)) = &self.pools else {
+            unreachable!("pools are created above");
+        };
+        if self.quad.is_none() {
+            let quad = FullscreenQuad::new(glow.glow())
+                .map_err(|err| glib::bool_error!("Failed to create quad: {err}"))?;
+            self.quad = Some(quad);
+        }
+        let Some(quad) = &self.quad else {
+            unreachable!("quad is created above");
+        };
+
+        // Buffers keep their textures out of the pools until the frame is done
+        let mut rendered: Vec<(gst::Buffer, gst_gl::GLMemory)> = Vec::new();
+        for (idx, pass) in self.passes.iter().enumerate() {
+            let target = match pools.get(idx) {
+                Some(pool) => {
+                    let buffer = pool
+                        .acquire_buffer(None)
+                        .map_err(|err| glib::bool_error!("Failed to acquire texture: {err}"))?;
+                    let memory = buffer
+                        .memory(0)
+                        .and_then(|memory| memory.downcast_memory::<gst_gl::GLMemory>().ok())
+                        .ok_or_else(|| glib::bool_error!("Pool buffer has no GL memory"))?;
+                    rendered.push((buffer, memory.clone()));
+                    memory
+                }
+                None => output.clone(),
+            };
+
+            let textures = pass
+                .inputs
+                .iter()
+                .map(|(sampler, source)| {
+                    let memory = match source {
+                        Source::Input => input,
+                        Source::Pass(name) => {
+                            let earlier = self.passes.iter().position(|p| p.name == *name);
+                            &rendered[earlier.expect("validated in new")].1
+                        }
+                    };
+                    (sampler.as_str(), memory.as_glow_texture())
+                })
+                .collect::<Vec<_>>();
+
+            filter.render_to_target(input, &target, |_, _| {
+                pass.shader.use_();
+                set_uniforms(pass);
+
+                // Every input gets its own texture unit
+                let gl = glow.glow();
+                for (unit, (sampler, texture)) in textures.iter().enumerate() {
+                    unsafe {
+                        gl.active_texture(glow::TEXTURE0 + unit as u32);
+                        gl.bind_texture(glow::TEXTURE_2D, *texture);
+                    }
+                    pass.shader.set_uniform_1i(sampler, unit as i32);
+                }
+
+                quad.draw(
+                    gl,
+                    pass.shader.attribute_location("a_position"),
+                    pass.shader.attribute_location("a_texcoord"),
+                );
+
+                // Leave the default unit active for whoever draws next
+                unsafe { gl.active_texture(glow::TEXTURE0) };
+
+                true
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the GL objects of the graph, must be called on the GL thread before dropping it
+    pub fn release(&mut self, gl: &glow::Context) {
+        if let Some(quad) = self.quad.take() {
+            quad.delete(gl);
+        }
+    }
+}
+
+impl Drop for RenderGraph {
+    fn drop(&mut self) {
+        if let Some((_, pools)) = self.pools.take() {
+            for pool in pools {
+                let _ = pool.set_active(false);
+            }
+        }
+    }
+}